@@ -0,0 +1,120 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_localserver::config::{Config, RouteConfig};
+use rust_localserver::http::{ParseState, Parser, Request};
+use rust_localserver::router::Router;
+
+const BODY_SIZE: usize = 1 << 20;
+// Matches the event loop's read buffer
+const READ_SIZE: usize = 4096;
+
+fn parse_in_reads(input: &[u8]) -> Parser {
+    let mut parser = Parser::new();
+    for read in input.chunks(READ_SIZE) {
+        parser.parse(read);
+    }
+    assert_eq!(parser.state, ParseState::Done);
+    parser
+}
+
+fn content_length_request() -> Vec<u8> {
+    let mut req = format!("POST /upload HTTP/1.1\r\nHost: bench\r\nContent-Length: {}\r\n\r\n", BODY_SIZE).into_bytes();
+    req.resize(req.len() + BODY_SIZE, b'x');
+    req
+}
+
+fn chunked_request(chunk_size: usize) -> Vec<u8> {
+    let mut req = b"POST /upload HTTP/1.1\r\nHost: bench\r\nTransfer-Encoding: chunked\r\n\r\n".to_vec();
+    let chunk = vec![b'x'; chunk_size];
+    for _ in 0..BODY_SIZE / chunk_size {
+        req.extend_from_slice(format!("{:x}\r\n", chunk_size).as_bytes());
+        req.extend_from_slice(&chunk);
+        req.extend_from_slice(b"\r\n");
+    }
+    req.extend_from_slice(b"0\r\n\r\n");
+    req
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parser");
+    group.throughput(Throughput::Bytes(BODY_SIZE as u64));
+
+    let request = content_length_request();
+    group.bench_function("content_length_1mib", |b| b.iter(|| parse_in_reads(black_box(&request))));
+
+    // Small chunks stress the per-chunk buffer handling
+    for chunk_size in [256, 16 * 1024] {
+        let request = chunked_request(chunk_size);
+        group.bench_with_input(BenchmarkId::new("chunked_1mib", chunk_size), &request, |b, request| {
+            b.iter(|| parse_in_reads(black_box(request)))
+        });
+    }
+    group.finish();
+}
+
+fn routes_config(count: usize) -> Config {
+    let mut yaml = String::from("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n");
+    for i in 0..count {
+        yaml.push_str(&format!("      - path: \"/section{}/page\"\n        root: \"/tmp\"\n", i));
+    }
+    yaml.push_str("      - path: \"/\"\n        root: \"/tmp\"\n");
+    serde_yaml::from_str(&yaml).unwrap()
+}
+
+fn router_with_routes(count: usize) -> Router {
+    Router::new(routes_config(count))
+}
+
+// The scan `find_route` replaced, kept as the baseline for its numbers
+fn linear_find_route<'a>(config: &'a Config, path: &str) -> Option<&'a RouteConfig> {
+    config.servers[0].routes.iter()
+        .filter(|r| path.starts_with(&r.path))
+        .max_by_key(|r| r.path.len())
+}
+
+fn bench_find_route(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find_route");
+    for count in [10, 100, 1000] {
+        let router = router_with_routes(count);
+        let hit = format!("/section{}/page/a/b/c.html", count / 2);
+        group.bench_with_input(BenchmarkId::new("deep_hit", count), &hit, |b, path| {
+            b.iter(|| router.find_route(0, black_box(path)).unwrap())
+        });
+        let config = routes_config(count);
+        group.bench_with_input(BenchmarkId::new("deep_hit_linear", count), &hit, |b, path| {
+            b.iter(|| linear_find_route(&config, black_box(path)).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("fallback_to_root", count), "/unmatched/a/b/c.html", |b, path| {
+            b.iter(|| router.find_route(0, black_box(path)).unwrap())
+        });
+    }
+    group.finish();
+}
+
+// A static GET whose route root is canonicalized once at startup, against the same file
+// under a `$host` root, which costs a canonicalize (an lstat per path component) per request
+fn bench_canonical_root(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("rust-localserver-bench-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("bench")).unwrap();
+    std::fs::write(dir.join("bench/index.html"), "hello").unwrap();
+    let yaml = format!(
+        "servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/cached\"\n        root: \"{dir}/bench\"\n      - path: \"/\"\n        root: \"{dir}/$host\"\n",
+        dir = dir.display()
+    );
+    let router = Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap());
+
+    let mut group = c.benchmark_group("canonical_root");
+    for (name, path) in [("cached", "/cached/index.html"), ("per_request", "/index.html")] {
+        let mut request = Request::new();
+        request.path = path.to_string();
+        request.headers.insert("Host".to_string(), "bench".to_string());
+        assert_eq!(router.handle(&request, 0).status_code, 200);
+        group.bench_with_input(BenchmarkId::new("static_get", name), &request, |b, request| {
+            b.iter(|| router.handle(black_box(request), 0))
+        });
+    }
+    group.finish();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+criterion_group!(benches, bench_parser, bench_find_route, bench_canonical_root);
+criterion_main!(benches);
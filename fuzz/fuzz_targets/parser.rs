@@ -0,0 +1,40 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use rust_localserver::http::{ParseState, Parser};
+
+const MAX_URI_LENGTH: usize = 256;
+
+fn parser() -> Parser {
+    let mut parser = Parser::new();
+    parser.max_uri_length = Some(MAX_URI_LENGTH);
+    parser
+}
+
+// The first byte picks the read size the rest of the input is split into, so
+// the fuzzer explores chunk boundaries along with the bytes themselves
+fuzz_target!(|data: &[u8]| {
+    let Some((&step, input)) = data.split_first() else { return };
+    let step = step as usize + 1;
+
+    let mut whole = parser();
+    whole.parse(input);
+
+    let mut split = parser();
+    for read in input.chunks(step) {
+        split.parse(read);
+        // Nothing is held beyond what was received
+        assert!(split.buffered() <= input.len());
+    }
+
+    assert_eq!(whole.state, split.state);
+    match whole.state {
+        ParseState::Done => {
+            assert_eq!(whole.request.path, split.request.path);
+            assert_eq!(whole.request.headers, split.request.headers);
+            assert_eq!(whole.request.body, split.request.body);
+        }
+        ParseState::Error => assert_eq!(whole.error_status, split.error_status),
+        _ => {}
+    }
+});
@@ -0,0 +1,95 @@
+use crate::config::LogFormat;
+use crate::utils::date::clf_date;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+pub struct AccessEntry<'a> {
+    pub request_id: u64,
+    pub peer: SocketAddr,
+    pub host: &'a str,
+    pub method: &'a str,
+    pub path: &'a str,
+    pub version: &'a str,
+    pub status: u16,
+    pub bytes: usize,
+    pub duration: Duration,
+}
+
+impl AccessEntry<'_> {
+    pub fn format(&self, format: &LogFormat) -> String {
+        let peer = self.peer.ip().to_string();
+        match format {
+            LogFormat::Common => format!(
+                "{} - - [{}] \"{} {} {}\" {} {}",
+                peer,
+                clf_date(SystemTime::now()),
+                self.method,
+                self.path,
+                self.version,
+                self.status,
+                self.bytes
+            ),
+            LogFormat::Json => format!(
+                "{{\"request_id\":{},\"peer\":\"{}\",\"host\":\"{}\",\"method\":\"{}\",\"path\":\"{}\",\"status\":{},\"bytes\":{},\"duration_ms\":{:.3}}}",
+                self.request_id,
+                json_escape(&peer),
+                json_escape(self.host),
+                json_escape(self.method),
+                json_escape(self.path),
+                self.status,
+                self.bytes,
+                self.duration.as_secs_f64() * 1000.0
+            ),
+        }
+    }
+}
+
+/// Whether a request is logged at `rate`. Decided by a hash of the request id, so
+/// the same id always gets the same answer.
+pub fn sampled(request_id: u64, rate: f64) -> bool {
+    if rate >= 1.0 {
+        return true;
+    }
+    // splitmix64 finalizer spreads sequential ids evenly over [0, 1)
+    let mut x = request_id.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^= x >> 31;
+    ((x >> 11) as f64 / (1u64 << 53) as f64) < rate
+}
+
+/// Debug rendering of a request body: text is escaped and cut at `max` bytes,
+/// anything else is summarized by its length and leading bytes in hex.
+pub fn body_preview(body: &[u8], max: usize) -> String {
+    let shown = &body[..body.len().min(max)];
+    // A cut may split a multi-byte character; that alone doesn't make it binary
+    let text = match std::str::from_utf8(shown) {
+        Ok(text) => Some(text),
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&shown[..e.valid_up_to()]).ok(),
+        Err(_) => None,
+    };
+    match text.filter(|t| !t.chars().any(|c| c.is_control() && !matches!(c, '\t' | '\r' | '\n'))) {
+        Some(text) if shown.len() < body.len() => format!("{:?}... ({} bytes)", text, body.len()),
+        Some(text) => format!("{:?}", text),
+        None => {
+            let hex: Vec<String> = body.iter().take(16).map(|b| format!("{:02x}", b)).collect();
+            format!("<binary, {} bytes: {}{}>", body.len(), hex.join(" "), if body.len() > 16 { " ..." } else { "" })
+        }
+    }
+}
+
+fn json_escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -1,18 +1,57 @@
 use std::process::{Command, Stdio};
-use std::io::Write;
+use std::io::{Read, Write};
 use std::collections::HashMap;
+use std::thread;
+use thiserror::Error;
+
+// Stderr kept for the error message; anything past this is read and dropped
+const MAX_STDERR: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum CgiError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+    #[error("{0}")]
+    Failed(String),
+    #[error("output exceeded {0} bytes")]
+    OutputTooLarge(usize),
+}
+
+pub type CgiHeaders = Vec<(String, String)>;
+
+/// Splits CGI output into its header block and body (RFC 3875 6.2). Returns `None`
+/// when the output doesn't start with a header block, so callers can send it as-is.
+pub fn split_output(output: &[u8]) -> Option<(CgiHeaders, &[u8])> {
+    let mut headers = Vec::new();
+    let mut pos = 0;
+    loop {
+        let end = output[pos..].iter().position(|&b| b == b'\n')? + pos;
+        let line = output[pos..end].strip_suffix(b"\r").unwrap_or(&output[pos..end]);
+        pos = end + 1;
+        if line.is_empty() {
+            return if headers.is_empty() { None } else { Some((headers, &output[pos..])) };
+        }
+        let line = std::str::from_utf8(line).ok()?;
+        let (name, value) = line.split_once(':')?;
+        if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c.is_control()) {
+            return None;
+        }
+        headers.push((name.to_string(), value.trim().to_string()));
+    }
+}
 
 pub struct CgiHandler {
     pub script_path: String,
     pub interpreter: String,
+    pub max_output: Option<usize>,
 }
 
 impl CgiHandler {
     pub fn new(script_path: String, interpreter: String) -> Self {
-        CgiHandler { script_path, interpreter }
+        CgiHandler { script_path, interpreter, max_output: None }
     }
 
-    pub fn execute(&self, env_vars: HashMap<String, String>, body: &[u8]) -> Result<Vec<u8>, std::io::Error> {
+    pub fn execute(&self, env_vars: HashMap<String, String>, body: &[u8]) -> Result<Vec<u8>, CgiError> {
         let mut child = Command::new(&self.interpreter)
             .arg(&self.script_path)
             .envs(env_vars)
@@ -21,18 +60,59 @@ impl CgiHandler {
             .stderr(Stdio::piped())
             .spawn()?;
 
-        if !body.is_empty() {
-            let mut stdin = child.stdin.take().unwrap();
-            stdin.write_all(body)?;
+        // Drain stderr alongside stdout: a script blocked on a full stderr pipe never
+        // closes stdout, and the read below would hang the server with it
+        let stderr = child.stderr.take().map(|mut pipe| {
+            thread::spawn(move || {
+                let mut kept = Vec::new();
+                let mut chunk = [0; 8192];
+                while let Ok(n) = pipe.read(&mut chunk) {
+                    if n == 0 {
+                        break;
+                    }
+                    let room = MAX_STDERR.saturating_sub(kept.len());
+                    kept.extend_from_slice(&chunk[..n.min(room)]);
+                }
+                kept
+            })
+        });
+
+        // Dropping stdin after the write signals EOF to the script
+        if let Some(mut stdin) = child.stdin.take() {
+            if !body.is_empty() {
+                stdin.write_all(body)?;
+            }
         }
 
-        let output = child.wait_with_output()?;
-        
-        if output.status.success() {
-            Ok(output.stdout)
+        // Read stdout incrementally so a runaway script can be stopped at the limit
+        let mut stdout = Vec::new();
+        if let Some(mut pipe) = child.stdout.take() {
+            let mut chunk = [0; 8192];
+            loop {
+                let n = pipe.read(&mut chunk)?;
+                if n == 0 {
+                    break;
+                }
+                stdout.extend_from_slice(&chunk[..n]);
+                if let Some(limit) = self.max_output {
+                    if stdout.len() > limit {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        // The stderr reader finishes on its own once the last writer is gone
+                        return Err(CgiError::OutputTooLarge(limit));
+                    }
+                }
+            }
+        }
+
+        let status = child.wait()?;
+        let stderr = stderr.and_then(|reader| reader.join().ok()).unwrap_or_default();
+
+        if status.success() {
+            Ok(stdout)
         } else {
-            let err = String::from_utf8_lossy(&output.stderr);
-            Err(std::io::Error::new(std::io::ErrorKind::Other, err))
+            let err = String::from_utf8_lossy(&stderr);
+            Err(CgiError::Failed(err.into_owned()))
         }
     }
 }
@@ -1,23 +1,212 @@
+use crate::utils::date::{http_date, parse_http_date};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
+use std::net::IpAddr;
 use std::path::Path;
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
     pub servers: Vec<ServerConfig>,
+    pub log_format: Option<LogFormat>,
+    pub max_accepts_per_sec: Option<u32>,
+    /// Bytes buffered across all connections before the heaviest are throttled: one with
+    /// output pending stops reading until it drains, one mid-request gets a 503.
+    pub max_total_buffer: Option<usize>,
+    pub max_routes: Option<usize>,
+    pub default_error_pages: Option<HashMap<u16, String>>,
+    pub close_on_error: Option<CloseOnError>,
+    pub max_uri_length: Option<usize>,
+    /// Seconds allowed for receiving a request body once the headers are in.
+    pub client_body_timeout: Option<u64>,
+    /// Limits on the header block a CGI script may send back; exceeding either is a 502.
+    pub max_response_headers: Option<usize>,
+    pub max_response_header_bytes: Option<usize>,
+    /// Active connection count above which a warning is logged; nothing is rejected.
+    pub soft_max_connections: Option<usize>,
+    /// Extension -> media type entries taking precedence over the built-in table.
+    pub mime_types: Option<HashMap<String, String>>,
+    /// Map lowercase spellings of known methods (`get`) to the method instead of `OTHER`.
+    pub lenient_methods: Option<bool>,
+    /// Fraction (0.0-1.0) of requests written to the access log.
+    pub log_sample_rate: Option<f64>,
+    pub relative_roots: Option<RelativeRoots>,
+    /// Readiness events handled per poll; defaults to 1024.
+    pub event_capacity: Option<usize>,
+    /// Seconds a connection may live in total before it is recycled.
+    pub max_connection_lifetime: Option<u64>,
+    /// `Retry-After` sent with 429 and 503 responses that don't set their own.
+    pub retry_after: Option<RetryAfter>,
+    /// Open connections allowed per client IP; extra ones get a 503 and are closed.
+    pub max_connections_per_ip: Option<usize>,
+    /// Named route lists that servers pull in with `use`.
+    pub route_templates: Option<HashMap<String, Vec<RouteConfig>>>,
+    /// Log up to this many bytes of each request body at debug level; off when unset.
+    pub log_bodies: Option<usize>,
+    pub no_host_match: Option<NoHostMatch>,
+    /// Path (e.g. `/__status`) answered with a plain-text table of open connections.
+    pub status_path: Option<String>,
+    /// Client IPs allowed to read `status_path`; defaults to loopback only.
+    pub status_allow: Option<Vec<IpAddr>>,
+    /// Send `Connection: keep-alive` on HTTP/1.1 responses that leave the connection open.
+    pub explicit_keep_alive: Option<bool>,
+}
+
+/// How a request is answered when its Host matches no `server_names` and no
+/// server is catch-all (no names) or marked `default_server`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum NoHostMatch {
+    /// Serve it from the first server block
+    FirstServer,
+    NotFound,
+    /// Close the connection without a response
+    Close,
+}
+
+/// A `Retry-After` value: delta-seconds (`120`) or an absolute IMF-fixdate.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum RetryAfter {
+    Seconds(u64),
+    Date(String),
+}
+
+impl RetryAfter {
+    pub fn header_value(&self) -> String {
+        match self {
+            RetryAfter::Seconds(secs) => secs.to_string(),
+            // Validated at load; re-formatting normalizes stray whitespace
+            RetryAfter::Date(date) => parse_http_date(date).map(http_date).unwrap_or_else(|| date.clone()),
+        }
+    }
+}
+
+/// How a relative route `root` is treated. The default resolves it against the
+/// working directory.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum RelativeRoots {
+    Cwd,
+    /// Resolve against the directory containing the config file
+    ConfigDir,
+    /// Reject relative roots when loading the config
+    Reject,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    Common,
+    Json,
+}
+
+/// Which error responses end a keep-alive connection. Malformed requests
+/// always close regardless of this setting.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum CloseOnError {
+    #[serde(rename = "4xx")]
+    ClientErrors,
+    #[serde(rename = "5xx")]
+    ServerErrors,
+    #[serde(rename = "always")]
+    Always,
+    #[serde(rename = "never")]
+    Never,
+}
+
+impl CloseOnError {
+    pub fn closes(self, status: u16) -> bool {
+        match self {
+            CloseOnError::ClientErrors => (400..500).contains(&status),
+            CloseOnError::ServerErrors => status >= 500,
+            CloseOnError::Always => status >= 400,
+            CloseOnError::Never => false,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct ServerConfig {
     pub host: String,
+    /// Omitted or empty means port 80.
+    #[serde(default)]
     pub ports: Vec<u16>,
     pub server_names: Option<Vec<String>>,
     pub error_pages: Option<HashMap<u16, String>>,
+    /// Largest request body accepted, in bytes; bigger ones get 413.
     pub client_max_body_size: Option<usize>,
+    pub verbose_errors: Option<bool>,
+    /// Send error bodies as JSON to clients that prefer `application/json` over HTML.
+    pub json_errors: Option<bool>,
+    pub max_query_params: Option<usize>,
+    pub max_query_length: Option<usize>,
+    pub options_unmatched: Option<OptionsUnmatched>,
+    pub absolute_redirects: Option<bool>,
+    pub strict_slash: Option<bool>,
+    pub listen_mode: Option<ListenMode>,
+    /// Port used in `redirect_https` Locations; 443 is omitted from the URL.
+    pub https_port: Option<u16>,
+    /// Static headers (e.g. `Alt-Svc`) added to every response unless already set.
+    pub extra_headers: Option<HashMap<String, String>>,
+    pub server_tokens: Option<ServerTokens>,
+    /// Methods allowed on routes that don't list `methods`; GET, HEAD, POST and OPTIONS if unset.
+    pub default_methods: Option<Vec<String>>,
+    /// Status for TRACE and CONNECT, which are never served: 405 (default) or 501.
+    pub trace_connect_status: Option<u16>,
+    /// Serve requests whose Host matches no server's `server_names`.
+    pub default_server: Option<bool>,
+    /// `route_templates` entry whose routes are added after this server's own;
+    /// a route the server defines itself wins over a template route with the same path.
+    #[serde(rename = "use")]
+    pub use_template: Option<String>,
+    #[serde(default)]
     pub routes: Vec<RouteConfig>,
 }
 
+// Used when a server lists no ports; there is no TLS listener to default to 443
+const DEFAULT_HTTP_PORTS: &[u16] = &[80];
+
+impl ServerConfig {
+    pub fn listen_ports(&self) -> &[u16] {
+        if self.ports.is_empty() {
+            DEFAULT_HTTP_PORTS
+        } else {
+            &self.ports
+        }
+    }
+
+    /// Name to use in absolute URLs when the request doesn't supply one: the first
+    /// `server_names` entry for wildcard binds, otherwise the bind address.
+    pub fn advertised_host(&self) -> &str {
+        let wildcard = matches!(self.host.as_str(), "0.0.0.0" | "::" | "[::]");
+        match self.server_names.as_ref().and_then(|names| names.first()) {
+            Some(name) if wildcard => name,
+            _ => &self.host,
+        }
+    }
+}
+
+/// How requests arriving on this server's listeners are answered.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ListenMode {
+    Http,
+    /// Answer every request with a 301 to the https equivalent URL
+    RedirectHttps,
+}
+
+/// How OPTIONS is answered when no route matches the path.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+pub enum OptionsUnmatched {
+    #[serde(rename = "404")]
+    NotFound,
+    #[serde(rename = "405")]
+    MethodNotAllowed,
+    #[serde(rename = "allow")]
+    Allow,
+}
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct RouteConfig {
     pub path: String,
@@ -28,12 +217,123 @@ pub struct RouteConfig {
     pub redirect: Option<String>,
     pub allow_uploads: Option<bool>,
     pub cgi_extensions: Option<HashMap<String, String>>,
+    pub cgi_max_output: Option<usize>,
+    pub cgi_env: Option<HashMap<String, String>>,
+    /// Limits on request headers forwarded to CGI as `HTTP_*` variables.
+    pub cgi_max_headers: Option<usize>,
+    pub cgi_max_header_bytes: Option<usize>,
+    pub accept_patch: Option<Vec<String>>,
+    pub brotli_static: Option<bool>,
+    pub cache_control: Option<HashMap<String, String>>,
+    pub autoindex_sort: Option<AutoindexSort>,
+    /// File name (e.g. `.autoindex`) that enables listing for the directory containing it.
+    pub autoindex_marker: Option<String>,
+    pub negotiate: Option<bool>,
+    /// Answer every request on this route with this status; 444 closes without a response.
+    pub return_status: Option<u16>,
+    /// File name looked up from the missing file's directory up to the root, served
+    /// instead of a 404 (e.g. `index.html` for single-page apps).
+    pub not_found_fallback: Option<String>,
+    pub not_found_fallback_status: Option<u16>,
+    /// Apply `Header: value` lines from a directory's `.headers` file and a file's
+    /// `<name>.headers` sidecar to static responses.
+    pub header_sidecars: Option<bool>,
+    /// Overrides the server's `server_tokens` for this route.
+    pub server_tokens: Option<ServerTokens>,
+    /// Never log request bodies sent to this route, even with `log_bodies`.
+    pub sensitive: Option<bool>,
+}
+
+/// Whether responses carry the `Server` header.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ServerTokens {
+    On,
+    Off,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AutoindexSort {
+    NameAsc,
+    NameDesc,
+    SizeAsc,
+    SizeDesc,
+    DateAsc,
+    DateDesc,
 }
 
 impl Config {
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
-        let content = fs::read_to_string(path)?;
-        let config: Config = serde_yaml::from_str(&content)?;
+        let content = fs::read_to_string(&path)?;
+        let mut config: Config = serde_yaml::from_str(&content)?;
+
+        for server in &mut config.servers {
+            let Some(name) = &server.use_template else { continue };
+            let template = config.route_templates.as_ref().and_then(|t| t.get(name))
+                .ok_or_else(|| format!("server {} uses unknown route template {}", server.host, name))?;
+            for route in template {
+                if !server.routes.iter().any(|r| r.path == route.path) {
+                    server.routes.push(route.clone());
+                }
+            }
+        }
+
+        match config.relative_roots.unwrap_or(RelativeRoots::Cwd) {
+            RelativeRoots::Cwd => {}
+            RelativeRoots::ConfigDir => {
+                let base = path.as_ref().parent().unwrap_or(Path::new(""));
+                for route in config.servers.iter_mut().flat_map(|s| &mut s.routes) {
+                    if let Some(root) = route.root.as_mut().filter(|r| Path::new(r.as_str()).is_relative()) {
+                        *root = base.join(&*root).to_string_lossy().into_owned();
+                    }
+                }
+            }
+            RelativeRoots::Reject => {
+                let relative = config.servers.iter().flat_map(|s| &s.routes)
+                    .find(|r| r.root.as_ref().is_some_and(|root| Path::new(root).is_relative()));
+                if let Some(route) = relative {
+                    return Err(format!("route {} has a relative root, but relative_roots is reject", route.path).into());
+                }
+            }
+        }
+
+        // Servers inherit any default error page they don't override
+        if let Some(defaults) = &config.default_error_pages {
+            for server in &mut config.servers {
+                let pages = server.error_pages.get_or_insert_with(HashMap::new);
+                for (status, path) in defaults {
+                    pages.entry(*status).or_insert_with(|| path.clone());
+                }
+            }
+        }
+
+        if config.servers.is_empty() {
+            return Err("config must define at least one server".into());
+        }
+        for server in &config.servers {
+            if server.routes.is_empty() {
+                return Err(format!("server {} has no routes", server.host).into());
+            }
+            if let Some(status) = server.trace_connect_status.filter(|s| !matches!(s, 405 | 501)) {
+                return Err(format!("server {} has trace_connect_status {}; use 405 or 501", server.host, status).into());
+            }
+        }
+        if config.servers.iter().filter(|s| s.default_server == Some(true)).count() > 1 {
+            return Err("only one server can be the default_server".into());
+        }
+
+        if let Some(RetryAfter::Date(date)) = &config.retry_after {
+            if parse_http_date(date).is_none() {
+                return Err(format!("retry_after {:?} is neither a number of seconds nor an HTTP-date", date).into());
+            }
+        }
+
+        if let Some(max) = config.max_routes {
+            if let Some(server) = config.servers.iter().find(|s| s.routes.len() > max) {
+                return Err(format!("server {} has {} routes, more than max_routes ({})", server.host, server.routes.len(), max).into());
+            }
+        }
         Ok(config)
     }
 }
@@ -1,24 +1,71 @@
 use crate::config::ServerConfig;
-use crate::http::Response;
+use crate::http::{media_quality, reason_phrase, Request, Response, SERVER_TOKEN};
+use crate::template;
 use std::fs;
 
-pub fn generate_error_response(status_code: u16, server_cfg: &ServerConfig) -> Response {
+const DEFAULT_ERROR_TEMPLATE: &str = "<h1>{{status}} Error</h1>";
+
+pub fn generate_error_response(status_code: u16, server_cfg: &ServerConfig, request: &Request) -> Response {
     let mut res = Response::new(status_code);
     
+    let status = status_code.to_string();
+    let vars = [
+        ("status", status.as_str()),
+        ("reason", reason_phrase(status_code)),
+        ("path", request.path.as_str()),
+        ("server", SERVER_TOKEN),
+    ];
+
+    if server_cfg.json_errors.unwrap_or(false) {
+        res.headers.insert("Vary".to_string(), "Accept".to_string());
+        let accept = request.headers.get("Accept").map(String::as_str).unwrap_or("*/*");
+        if media_quality(accept, "application/json") > media_quality(accept, "text/html") {
+            res.body = format!("{{\"status\":{},\"error\":\"{}\"}}", status_code, reason_phrase(status_code)).into_bytes();
+            res.headers.insert("Content-Type".to_string(), "application/json".to_string());
+            return res;
+        }
+    }
+
     if let Some(error_pages) = &server_cfg.error_pages {
         if let Some(path) = error_pages.get(&status_code) {
             if let Ok(content) = fs::read(path) {
-                res.body = content;
+                // Text pages may use {{status}}, {{reason}}, {{path}} and {{server}}
+                res.body = match String::from_utf8(content) {
+                    Ok(text) => template::render(&text, &vars).into_bytes(),
+                    Err(e) => e.into_bytes(),
+                };
                 res.headers.insert("Content-Type".to_string(), "text/html".to_string());
-                res.headers.insert("Content-Length".to_string(), res.body.len().to_string());
                 return res;
             }
         }
     }
 
     // Default error body
-    res.body = format!("<h1>{} Error</h1>", status_code).into_bytes();
+    let mut body = template::render(DEFAULT_ERROR_TEMPLATE, &vars);
+    if server_cfg.verbose_errors.unwrap_or(false) {
+        // Echo the request for debugging; escaped since the path is attacker-controlled
+        body.push_str(&format!(
+            "<p>{} {}</p>",
+            html_escape(request.method.as_str()),
+            html_escape(&request.path)
+        ));
+    }
+    res.body = body.into_bytes();
     res.headers.insert("Content-Type".to_string(), "text/html".to_string());
-    res.headers.insert("Content-Length".to_string(), res.body.len().to_string());
     res
 }
+
+pub fn html_escape(input: &str) -> String {
+    let mut out = String::with_capacity(input.len());
+    for c in input.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 
 #[derive(Debug)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum Method {
     GET,
+    HEAD,
     POST,
+    PUT,
     DELETE,
+    OPTIONS,
     OTHER(String),
 }
 
@@ -12,17 +16,65 @@ impl From<&str> for Method {
     fn from(s: &str) -> Self {
         match s {
             "GET" => Method::GET,
+            "HEAD" => Method::HEAD,
             "POST" => Method::POST,
+            "PUT" => Method::PUT,
             "DELETE" => Method::DELETE,
+            "OPTIONS" => Method::OPTIONS,
             _ => Method::OTHER(s.to_string()),
         }
     }
 }
 
+// IANA HTTP Method Registry entries without a variant of their own
+const REGISTERED_METHODS: &[&str] = &[
+    "ACL", "BASELINE-CONTROL", "BIND", "CHECKIN", "CHECKOUT", "CONNECT", "COPY", "LABEL", "LINK", "LOCK",
+    "MERGE", "MKACTIVITY", "MKCALENDAR", "MKCOL", "MKREDIRECTREF", "MKWORKSPACE", "MOVE", "ORDERPATCH",
+    "PATCH", "PROPFIND", "PROPPATCH", "REBIND", "REPORT", "SEARCH", "TRACE", "UNBIND", "UNCHECKOUT",
+    "UNLINK", "UNLOCK", "UPDATE", "UPDATEREDIRECTREF", "VERSION-CONTROL",
+];
+
+impl Method {
+    /// Like `From<&str>`, but with `lenient` known methods match case-insensitively.
+    pub fn parse(s: &str, lenient: bool) -> Self {
+        if lenient {
+            let upper = s.to_ascii_uppercase();
+            if let method @ (Method::GET | Method::HEAD | Method::POST | Method::PUT | Method::DELETE | Method::OPTIONS) =
+                Method::from(upper.as_str())
+            {
+                return method;
+            }
+        }
+        Method::from(s)
+    }
+
+    /// Whether the method is one the server recognizes: a named variant or an entry in
+    /// the IANA method registry. Routes refuse these with 405; anything else is 501.
+    pub fn is_known(&self) -> bool {
+        match self {
+            Method::OTHER(s) => REGISTERED_METHODS.contains(&s.as_str()),
+            _ => true,
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::GET => "GET",
+            Method::HEAD => "HEAD",
+            Method::POST => "POST",
+            Method::PUT => "PUT",
+            Method::DELETE => "DELETE",
+            Method::OPTIONS => "OPTIONS",
+            Method::OTHER(s) => s,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Request {
     pub method: Method,
     pub path: String,
+    pub query: Option<String>,
     pub version: String,
     pub headers: HashMap<String, String>,
     pub body: Vec<u8>,
@@ -33,6 +85,7 @@ impl Request {
         Request {
             method: Method::GET,
             path: String::new(),
+            query: None,
             version: String::new(),
             headers: HashMap::new(),
             body: Vec::new(),
@@ -40,6 +93,12 @@ impl Request {
     }
 }
 
+impl Default for Request {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub enum ParseState {
     RequestLine,
@@ -52,9 +111,18 @@ pub enum ParseState {
     Error,
 }
 
+/// Room a request line gets beyond `max_uri_length` for everything besides the target:
+/// the method (longest registered is 17 bytes), two spaces and `HTTP/1.1`, with headroom.
+pub const REQUEST_LINE_SLACK: usize = 64;
+
 pub struct Parser {
     pub state: ParseState,
     pub request: Request,
+    /// Status to answer with once `state` is `Error`.
+    pub error_status: u16,
+    /// Longest request target accepted, checked before and after percent-decoding.
+    pub max_uri_length: Option<usize>,
+    pub lenient_methods: bool,
     buffer: Vec<u8>,
     chunk_size: usize,
 }
@@ -64,23 +132,95 @@ impl Parser {
         Parser {
             state: ParseState::RequestLine,
             request: Request::new(),
+            error_status: 400,
+            max_uri_length: None,
+            lenient_methods: false,
             buffer: Vec::new(),
             chunk_size: 0,
         }
     }
 
+    /// Prepares for the next request on the connection, keeping any bytes already
+    /// received past the end of the previous one and the configured limits.
+    pub fn reset(&mut self) {
+        self.state = ParseState::RequestLine;
+        self.request = Request::new();
+        self.error_status = 400;
+        self.chunk_size = 0;
+    }
+
+    /// Content-Length declared by the request in progress, if it sent a valid one.
+    pub fn content_length(&self) -> Option<usize> {
+        self.request.headers.get("Content-Length").and_then(|l| parse_content_length(l))
+    }
+
+    /// Drops the request in progress and any bytes received after it.
+    pub fn discard(&mut self) {
+        self.reset();
+        self.buffer = Vec::new();
+    }
+
+    /// Bytes held by the parser: unparsed input plus the body collected so far.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len() + self.request.body.len()
+    }
+
     pub fn parse(&mut self, data: &[u8]) {
         self.buffer.extend_from_slice(data);
 
         loop {
             match self.state {
                 ParseState::RequestLine => {
+                    // RFC 7230 3.5: ignore empty lines received before the request line
+                    while self.buffer.starts_with(b"\r\n") {
+                        self.buffer.drain(..2);
+                    }
                     if let Some(pos) = self.buffer.windows(2).position(|w| w == b"\r\n") {
+                        // Same bound as for an incomplete line below, so the outcome doesn't
+                        // depend on where reads split the request
+                        if self.max_uri_length.is_some_and(|max| pos > max + REQUEST_LINE_SLACK) {
+                            self.error_status = 414;
+                            self.state = ParseState::Error;
+                            return;
+                        }
                         let line = String::from_utf8_lossy(&self.buffer[..pos]);
                         let parts: Vec<&str> = line.split_whitespace().collect();
                         if parts.len() == 3 {
-                            self.request.method = Method::from(parts[0]);
-                            self.request.path = parts[1].to_string();
+                            if let Some(max) = self.max_uri_length {
+                                if parts[1].len() > max || percent_decode(parts[1]).len() > max {
+                                    self.error_status = 414;
+                                    self.state = ParseState::Error;
+                                    return;
+                                }
+                            }
+                            // A NUL in the path would truncate filesystem and CGI arguments
+                            if percent_decode(parts[1]).contains(&0) {
+                                self.state = ParseState::Error;
+                                return;
+                            }
+                            // Includes the HTTP/2 preface `PRI * HTTP/2.0`, which would otherwise
+                            // be misread as an HTTP/1 request
+                            if parts[2].starts_with("HTTP/") && !parts[2].starts_with("HTTP/1.") {
+                                self.error_status = 505;
+                                self.state = ParseState::Error;
+                                return;
+                            }
+                            self.request.method = Method::parse(parts[0], self.lenient_methods);
+                            let (path, query) = match parts[1].split_once('?') {
+                                Some((path, query)) => (path, Some(query.to_string())),
+                                None => (parts[1], None),
+                            };
+                            // Routing and filesystem lookup see the decoded, normalized path;
+                            // a `..` climbing above `/` is rejected outright
+                            let decoded = String::from_utf8(percent_decode(path)).ok();
+                            match decoded.as_deref().and_then(normalize_path) {
+                                Some(path) => self.request.path = path,
+                                None => {
+                                    self.state = ParseState::Error;
+                                    return;
+                                }
+                            }
+                            self.request.query = query;
                             self.request.version = parts[2].to_string();
                             self.state = ParseState::Headers;
                             self.buffer.drain(..pos + 2);
@@ -89,6 +229,16 @@ impl Parser {
                             return;
                         }
                     } else {
+                        // Don't keep buffering a request line that can only end up too long
+                        // A trailing CR may be the start of the line ending, not part of the line
+                        let line_len = self.buffer.len() - usize::from(self.buffer.ends_with(b"\r"));
+                        if let Some(max) = self.max_uri_length {
+                            if line_len > max + REQUEST_LINE_SLACK {
+                                self.error_status = 414;
+                                self.state = ParseState::Error;
+                                return;
+                            }
+                        }
                         break;
                     }
                 }
@@ -96,6 +246,14 @@ impl Parser {
                     if let Some(pos) = self.buffer.windows(2).position(|w| w == b"\r\n") {
                         if pos == 0 {
                             self.buffer.drain(..2);
+                            // RFC 7231 5.1.1: 100-continue is the only defined expectation
+                            if let Some(expect) = self.request.headers.get("Expect") {
+                                if !expect.eq_ignore_ascii_case("100-continue") {
+                                    self.error_status = 417;
+                                    self.state = ParseState::Error;
+                                    return;
+                                }
+                            }
                             if let Some(te) = self.request.headers.get("Transfer-Encoding") {
                                 if te.to_lowercase() == "chunked" {
                                     self.state = ParseState::ChunkSize;
@@ -104,7 +262,7 @@ impl Parser {
                                     return;
                                 }
                             } else if let Some(len_str) = self.request.headers.get("Content-Length") {
-                                if let Ok(len) = len_str.parse::<usize>() {
+                                if let Some(len) = parse_content_length(len_str) {
                                     if len == 0 {
                                         self.state = ParseState::Done;
                                     } else {
@@ -118,10 +276,23 @@ impl Parser {
                                 self.state = ParseState::Done;
                             }
                         } else {
+                            // Obsolete line folding is a smuggling vector (RFC 7230 3.2.4), and so are
+                            // control bytes other than tab (NUL, bare CR, VT, DEL, ...)
+                            let has_control = self.buffer[..pos].iter().any(|&b| (b < 0x20 && b != b'\t') || b == 0x7f);
+                            if self.buffer[0] == b' ' || self.buffer[0] == b'\t' || has_control {
+                                self.state = ParseState::Error;
+                                return;
+                            }
                             let line = String::from_utf8_lossy(&self.buffer[..pos]);
                             if let Some(colon) = line.find(':') {
-                                let key = line[..colon].trim().to_string();
+                                let key = canonical_header_name(line[..colon].trim());
                                 let value = line[colon + 1..].trim().to_string();
+                                // Two different lengths leave the body's end ambiguous (RFC 7230 3.3.3)
+                                let framing = key == "Content-Length" || key == "Transfer-Encoding";
+                                if framing && self.request.headers.get(&key).is_some_and(|v| *v != value) {
+                                    self.state = ParseState::Error;
+                                    return;
+                                }
                                 self.request.headers.insert(key, value);
                             }
                             self.buffer.drain(..pos + 2);
@@ -132,7 +303,7 @@ impl Parser {
                 }
                 ParseState::Body => {
                     let content_length = self.request.headers.get("Content-Length")
-                        .and_then(|l| l.parse::<usize>().ok())
+                        .and_then(|l| parse_content_length(l))
                         .unwrap_or(0);
                     
                     if self.buffer.len() >= content_length {
@@ -145,7 +316,9 @@ impl Parser {
                 ParseState::ChunkSize => {
                     if let Some(pos) = self.buffer.windows(2).position(|w| w == b"\r\n") {
                         let line = String::from_utf8_lossy(&self.buffer[..pos]);
-                        if let Ok(size) = usize::from_str_radix(line.trim(), 16) {
+                        // Chunk extensions (`5;name=value`) carry nothing we use
+                        let size = line.split(';').next().unwrap_or("");
+                        if let Ok(size) = usize::from_str_radix(size.trim(), 16) {
                             self.chunk_size = size;
                             self.buffer.drain(..pos + 2);
                             if size == 0 {
@@ -162,7 +335,8 @@ impl Parser {
                     }
                 }
                 ParseState::ChunkData => {
-                    if self.buffer.len() >= self.chunk_size + 2 {
+                    // A chunk size near usize::MAX must not overflow the CRLF allowance
+                    if self.buffer.len() >= self.chunk_size.saturating_add(2) {
                         self.request.body.extend_from_slice(&self.buffer[..self.chunk_size]);
                         self.buffer.drain(..self.chunk_size + 2);
                         self.state = ParseState::ChunkSize;
@@ -188,6 +362,166 @@ impl Parser {
     }
 }
 
+impl Default for Parser {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Collapses repeated slashes and resolves `.` and `..` segments (RFC 3986 5.2.4),
+/// keeping a trailing slash. Returns `None` if `..` would climb above the root.
+/// Targets that aren't origin-form (such as `*`) are returned unchanged.
+pub fn normalize_path(path: &str) -> Option<String> {
+    if !path.starts_with('/') {
+        return Some(path.to_string());
+    }
+    let mut segments: Vec<&str> = Vec::new();
+    let mut trailing_slash = path.ends_with('/');
+    for segment in path.split('/') {
+        match segment {
+            "" => {}
+            "." => trailing_slash = true,
+            ".." => {
+                segments.pop()?;
+                trailing_slash = true;
+            }
+            s => {
+                segments.push(s);
+                trailing_slash = false;
+            }
+        }
+    }
+    trailing_slash |= path.ends_with('/');
+    let mut normalized = format!("/{}", segments.join("/"));
+    if trailing_slash && !segments.is_empty() {
+        normalized.push('/');
+    }
+    Some(normalized)
+}
+
+/// Header names are case-insensitive; storing them as `Content-Length` lets lookups
+/// use one spelling whatever the client sent.
+fn canonical_header_name(name: &str) -> String {
+    let mut canonical = String::with_capacity(name.len());
+    let mut word_start = true;
+    for c in name.chars() {
+        canonical.push(if word_start { c.to_ascii_uppercase() } else { c.to_ascii_lowercase() });
+        word_start = c == '-';
+    }
+    canonical
+}
+
+/// Parses a Content-Length value, tolerating surrounding whitespace and a leading `+`
+/// that some clients send. Anything else non-numeric is rejected.
+fn parse_content_length(value: &str) -> Option<usize> {
+    let digits = value.trim();
+    let digits = digits.strip_prefix('+').unwrap_or(digits);
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Decodes `%XX` escapes, leaving malformed escapes as-is.
+pub fn percent_decode(input: &str) -> Vec<u8> {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok());
+            if let Some(b) = hex {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    out
+}
+
+/// Parses an `Accept`/`Accept-Encoding` style list into `(token, q)` pairs,
+/// highest q first. Entries with `q=0` are kept so callers can tell an explicit
+/// refusal apart from a wildcard match.
+pub fn parse_accept(header: &str) -> Vec<(String, f32)> {
+    let mut items: Vec<(String, f32)> = header.split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let token = parts.next()?.trim();
+            if token.is_empty() {
+                return None;
+            }
+            let q = match parts.find_map(|p| p.trim().strip_prefix("q=")) {
+                Some(q) => q.trim().parse::<f32>().ok().filter(|q| (0.0..=1.0).contains(q))?,
+                None => 1.0,
+            };
+            Some((token.to_string(), q))
+        })
+        .collect();
+    items.sort_by(|a, b| b.1.total_cmp(&a.1));
+    items
+}
+
+/// The q-value an Accept header gives `mime`, using the most specific matching range.
+pub fn media_quality(accept: &str, mime: &str) -> f32 {
+    let (kind, _) = mime.split_once('/').unwrap_or((mime, ""));
+    let mut best: Option<(u8, f32)> = None;
+    for (range, q) in parse_accept(accept) {
+        let specificity = if range.eq_ignore_ascii_case(mime) {
+            2
+        } else if range.split_once('/').is_some_and(|(t, s)| t.eq_ignore_ascii_case(kind) && s == "*") {
+            1
+        } else if range == "*/*" {
+            0
+        } else {
+            continue;
+        };
+        if best.is_none_or(|(s, _)| specificity > s) {
+            best = Some((specificity, q));
+        }
+    }
+    best.map_or(0.0, |(_, q)| q)
+}
+
+pub const SERVER_TOKEN: &str = "RustLocalServer/0.1.0";
+/// Non-standard status (after nginx) telling the server to close without sending anything.
+pub const CLOSE_WITHOUT_RESPONSE: u16 = 444;
+
+pub fn reason_phrase(status_code: u16) -> &'static str {
+    match status_code {
+        200 => "OK",
+        201 => "Created",
+        204 => "No Content",
+        301 => "Moved Permanently",
+        302 => "Found",
+        304 => "Not Modified",
+        400 => "Bad Request",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        408 => "Request Timeout",
+        413 => "Payload Too Large",
+        414 => "URI Too Long",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        429 => "Too Many Requests",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        505 => "HTTP Version Not Supported",
+        _ => "Unknown",
+    }
+}
+
+/// Whether a response with this status may carry a body, and so a Content-Length.
+pub fn has_body(status: u16) -> bool {
+    !(100..200).contains(&status) && status != 204 && status != 304
+}
+
 pub struct Response {
     pub status_code: u16,
     pub headers: HashMap<String, String>,
@@ -197,7 +531,7 @@ pub struct Response {
 impl Response {
     pub fn new(status_code: u16) -> Self {
         let mut headers = HashMap::new();
-        headers.insert("Server".to_string(), "RustLocalServer/0.1.0".to_string());
+        headers.insert("Server".to_string(), SERVER_TOKEN.to_string());
         Response {
             status_code,
             headers,
@@ -205,25 +539,16 @@ impl Response {
         }
     }
 
+    /// Content-Length comes from the body unless a handler already set it (HEAD keeps the
+    /// GET length after dropping the body); 1xx, 204 and 304 never carry one.
     pub fn to_bytes(&self) -> Vec<u8> {
-        let status_text = match self.status_code {
-            200 => "OK",
-            201 => "Created",
-            204 => "No Content",
-            301 => "Moved Permanently",
-            400 => "Bad Request",
-            403 => "Forbidden",
-            404 => "Not Found",
-            405 => "Method Not Allowed",
-            413 => "Payload Too Large",
-            500 => "Internal Server Error",
-            _ => "Unknown",
-        };
-
-        let mut resp = format!("HTTP/1.1 {} {}\r\n", self.status_code, status_text).into_bytes();
+        let mut resp = format!("HTTP/1.1 {} {}\r\n", self.status_code, reason_phrase(self.status_code)).into_bytes();
         for (key, value) in &self.headers {
             resp.extend_from_slice(format!("{}: {}\r\n", key, value).as_bytes());
         }
+        if has_body(self.status_code) && !self.headers.keys().any(|k| k.eq_ignore_ascii_case("Content-Length")) {
+            resp.extend_from_slice(format!("Content-Length: {}\r\n", self.body.len()).as_bytes());
+        }
         resp.extend_from_slice(b"\r\n");
         resp.extend_from_slice(&self.body);
         resp
@@ -0,0 +1,15 @@
+//! Library half of the server, so benchmarks and tests can reach the parser and router.
+
+pub mod access_log;
+pub mod cgi;
+pub mod config;
+pub mod error;
+pub mod http;
+pub mod router;
+pub mod server;
+pub mod template;
+pub mod utils {
+    pub mod cookie;
+    pub mod date;
+    pub mod session;
+}
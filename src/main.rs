@@ -1,15 +1,5 @@
-mod config;
-mod server;
-mod router;
-mod http;
-mod cgi;
-mod error;
-mod utils {
-    pub mod cookie;
-    pub mod session;
-}
-
-use config::Config;
+use rust_localserver::config::Config;
+use rust_localserver::server;
 use std::env;
 use std::process;
 
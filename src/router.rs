@@ -1,71 +1,244 @@
-use crate::config::{Config, RouteConfig, ServerConfig};
-use crate::http::{Request, Response, Method};
-use crate::cgi::CgiHandler;
-use crate::error::generate_error_response;
+use crate::config::{AutoindexSort, Config, ListenMode, NoHostMatch, OptionsUnmatched, RouteConfig, ServerConfig, ServerTokens};
+use crate::http::{has_body, media_quality, parse_accept, Request, Response, Method, CLOSE_WITHOUT_RESPONSE, SERVER_TOKEN};
+use crate::template;
+use crate::utils::date::{http_date, parse_http_date};
+use crate::cgi::{self, CgiError, CgiHandler};
+use crate::error::{generate_error_response, html_escape};
 use std::collections::HashMap;
 use std::fs;
-use std::path::{Path, PathBuf};
+use std::io::{Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Methods allowed on routes that don't restrict `methods`, unless the server sets `default_methods`
+const DEFAULT_ALLOW: &[&str] = &["GET", "HEAD", "POST", "OPTIONS"];
+const DEFAULT_ACCEPT_PATCH: &str = "application/octet-stream";
+const HOST_VAR: &str = "$host";
+// Keep the CGI environment well under typical OS limits by default
+const DEFAULT_CGI_MAX_HEADERS: usize = 64;
+const DEFAULT_CGI_MAX_HEADER_BYTES: usize = 16 * 1024;
+const SIDECAR_SUFFIX: &str = ".headers";
+const DEFAULT_MAX_RESPONSE_HEADERS: usize = 100;
+const DEFAULT_MAX_RESPONSE_HEADER_BYTES: usize = 32 * 1024;
+const AUTOINDEX_HEADER: &str = "<html><head><title>Index of {{path}}</title></head><body><h1>Index of {{path}}</h1><ul>";
+const AUTOINDEX_FOOTER: &str = "</ul><hr><address>{{server}}</address></body></html>";
 
 pub struct Router {
     config: Config,
+    // Canonical form of each configured root, resolved once so traversal checks
+    // only canonicalize the request target
+    canonical_roots: HashMap<String, PathBuf>,
+    // Per server (same order as `config.servers`), route path -> route index
+    route_maps: Vec<HashMap<String, usize>>,
+    // `mime_types` keyed by lowercase extension without the dot
+    mime_overrides: HashMap<String, String>,
 }
 
 impl Router {
     pub fn new(config: Config) -> Self {
-        Router { config }
+        let mut canonical_roots = HashMap::new();
+        for route in config.servers.iter().flat_map(|s| &s.routes) {
+            if let Some(root) = route.root.as_ref().filter(|r| !r.contains(HOST_VAR)) {
+                if let Ok(canonical) = fs::canonicalize(root) {
+                    canonical_roots.insert(root.clone(), canonical);
+                }
+            }
+        }
+        let route_maps = config.servers.iter()
+            .map(|s| {
+                let strict = s.strict_slash.unwrap_or(true);
+                s.routes.iter().enumerate().map(|(i, r)| (route_key(&r.path, strict), i)).collect()
+            })
+            .collect();
+        let mime_overrides = config.mime_types.iter().flatten()
+            .map(|(ext, mime)| (ext.trim_start_matches('.').to_ascii_lowercase(), mime.clone()))
+            .collect();
+        Router { config, canonical_roots, route_maps, mime_overrides }
     }
 
-    pub fn handle(&self, request: &Request) -> Response {
+    /// `listener` is the index of the server block whose listener accepted the connection.
+    pub fn handle(&self, request: &Request, listener: usize) -> Response {
+        // Config loading rejects an empty server list, but don't panic if one slips through
+        if self.config.servers.is_empty() {
+            return Response::new(500);
+        }
         let host = request.headers.get("Host").cloned().unwrap_or_default();
-        let server_cfg = self.config.servers.iter().find(|s| {
+        let server_idx = match self.select_server(&host) {
+            Some(idx) => idx,
+            None if self.config.no_host_match == Some(NoHostMatch::Close) => {
+                return Response::new(CLOSE_WITHOUT_RESPONSE);
+            }
+            None => {
+                let mut res = generate_error_response(404, &self.config.servers[listener], request);
+                self.finalize(&mut res, request, listener);
+                return res;
+            }
+        };
+
+        let mut res = self.dispatch(request, listener, &host, server_idx);
+        self.finalize(&mut res, request, server_idx);
+        // HEAD gets the GET headers, Content-Length included, without the body
+        if matches!(request.method, Method::HEAD) {
+            if has_body(res.status_code) && !res.headers.keys().any(|k| k.eq_ignore_ascii_case("Content-Length")) {
+                res.headers.insert("Content-Length".to_string(), res.body.len().to_string());
+            }
+            res.body.clear();
+        }
+        res
+    }
+
+    /// `client_max_body_size` of the server that will answer `request`.
+    pub fn body_limit(&self, request: &Request, listener: usize) -> Option<usize> {
+        let host = request.headers.get("Host").map(String::as_str).unwrap_or("");
+        let server_idx = self.select_server(host).unwrap_or(listener);
+        self.config.servers.get(server_idx)?.client_max_body_size
+    }
+
+    /// The route `handle` would use for this request, if any.
+    pub fn route_for(&self, request: &Request) -> Option<&RouteConfig> {
+        let host = request.headers.get("Host").map(String::as_str).unwrap_or("");
+        self.find_route(self.select_server(host)?, &request.path)
+    }
+
+    // None only when nothing matches and `no_host_match` says not to fall back
+    fn select_server(&self, host: &str) -> Option<usize> {
+        let servers = &self.config.servers;
+        servers.iter().position(|s| {
             if let Some(names) = &s.server_names {
                 names.iter().any(|n| host.contains(n))
             } else {
                 true
             }
-        }).unwrap_or(&self.config.servers[0]);
+        })
+        .or_else(|| servers.iter().position(|s| s.default_server == Some(true)))
+        .or_else(|| match self.config.no_host_match.unwrap_or(NoHostMatch::FirstServer) {
+            NoHostMatch::FirstServer => Some(0),
+            NoHostMatch::NotFound | NoHostMatch::Close => None,
+        })
+    }
+
+    // Server- and route-wide header policy, applied whichever branch produced the response
+    fn finalize(&self, res: &mut Response, request: &Request, server_idx: usize) {
+        let server_cfg = &self.config.servers[server_idx];
+        if let Some(extra) = &server_cfg.extra_headers {
+            for (name, value) in extra {
+                res.headers.entry(name.clone()).or_insert_with(|| value.clone());
+            }
+        }
+        let tokens = self.find_route(server_idx, &request.path)
+            .and_then(|route| route.server_tokens)
+            .or(server_cfg.server_tokens)
+            .unwrap_or(ServerTokens::On);
+        if tokens == ServerTokens::Off {
+            res.headers.remove("Server");
+        }
+        if matches!(res.status_code, 429 | 503) {
+            if let Some(retry_after) = &self.config.retry_after {
+                res.headers.entry("Retry-After".to_string()).or_insert_with(|| retry_after.header_value());
+            }
+        }
+    }
+
+    fn dispatch(&self, request: &Request, listener: usize, host: &str, server_idx: usize) -> Response {
+        if !host.is_empty() && !valid_host(host) {
+            return generate_error_response(400, &self.config.servers[server_idx], request);
+        }
+        let listener_cfg = &self.config.servers[listener];
+        if listener_cfg.listen_mode == Some(ListenMode::RedirectHttps) {
+            let mut res = Response::new(301);
+            res.headers.insert("Location".to_string(), https_location(host, request, listener_cfg));
+            return res;
+        }
+        let server_cfg = &self.config.servers[server_idx];
+
+        if server_cfg.client_max_body_size.is_some_and(|max| request.body.len() > max) {
+            return generate_error_response(413, server_cfg, request);
+        }
+
+        if let Some(query) = &request.query {
+            let too_long = server_cfg.max_query_length.is_some_and(|max| query.len() > max);
+            let too_many = server_cfg.max_query_params
+                .is_some_and(|max| query.split('&').filter(|p| !p.is_empty()).count() > max);
+            if too_long || too_many {
+                return generate_error_response(400, server_cfg, request);
+            }
+        }
 
-        let route = match self.find_route(server_cfg, &request.path) {
+        // No tunneling, and TRACE would echo credentials back; refuse both before routing,
+        // since a CONNECT target (`host:443`) isn't a path
+        if matches!(request.method.as_str(), "TRACE" | "CONNECT") {
+            let status = server_cfg.trace_connect_status.unwrap_or(405);
+            let mut res = generate_error_response(status, server_cfg, request);
+            if status == 405 {
+                let methods = match self.find_route(server_idx, &request.path) {
+                    Some(route) => allowed_methods(route, server_cfg),
+                    None => default_methods(server_cfg),
+                };
+                res.headers.insert("Allow".to_string(), methods.join(", "));
+            }
+            return res;
+        }
+
+        let route = match self.find_route(server_idx, &request.path) {
             Some(r) => r,
-            None => return generate_error_response(404, server_cfg),
+            None if matches!(request.method, Method::OPTIONS) => {
+                return self.handle_unmatched_options(request, server_cfg);
+            }
+            None => return generate_error_response(404, server_cfg, request),
         };
 
-        if let Some(methods) = &route.methods {
-            let method_str = match request.method {
-                Method::GET => "GET",
-                Method::POST => "POST",
-                Method::DELETE => "DELETE",
-                Method::OTHER(ref s) => s,
-            };
-            if !methods.contains(&method_str.to_string()) {
-                return generate_error_response(405, server_cfg);
+        if let Some(status) = route.return_status {
+            if status == CLOSE_WITHOUT_RESPONSE {
+                return Response::new(status);
             }
+            return generate_error_response(status, server_cfg, request);
+        }
+
+        if matches!(request.method, Method::OPTIONS) {
+            return self.handle_options(route, server_cfg);
+        }
+
+        let methods = allowed_methods(route, server_cfg);
+        if !methods.iter().any(|m| m == request.method.as_str()) {
+            // 405 is for methods the server knows but this route refuses
+            if !request.method.is_known() {
+                return generate_error_response(501, server_cfg, request);
+            }
+            let mut res = generate_error_response(405, server_cfg, request);
+            res.headers.insert("Allow".to_string(), methods.join(", "));
+            return res;
         }
 
         if let Some(redirect) = &route.redirect {
             let mut res = Response::new(301);
-            res.headers.insert("Location".to_string(), redirect.clone());
+            let location = if server_cfg.absolute_redirects.unwrap_or(false) {
+                absolute_location(redirect, request, server_cfg)
+            } else {
+                redirect.clone()
+            };
+            res.headers.insert("Location".to_string(), location);
             return res;
         }
 
         // Handle CGI
         if let Some(cgi_exts) = &route.cgi_extensions {
-            let path = Path::new(&request.path);
-            if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                let ext_dot = format!(".{}", ext);
-                if let Some(interpreter) = cgi_exts.get(&ext_dot) {
-                    let mut script_path = PathBuf::from(route.root.as_deref().unwrap_or("."));
-                    let relative_path = request.path.strip_prefix(&route.path).unwrap_or(&request.path);
-                    script_path.push(relative_path.trim_start_matches('/'));
-
-                    return self.handle_cgi(request, script_path.to_str().unwrap(), interpreter);
-                }
+            if let Some((script_name, path_info, interpreter)) = split_script_path(&request.path, cgi_exts) {
+                let script_path = match self.resolve_path(route, request, script_name, ".") {
+                    Some(p) => p,
+                    None => return generate_error_response(403, server_cfg, request),
+                };
+
+                return self.handle_cgi(request, route, server_cfg, &script_path, script_name, path_info, interpreter);
             }
         }
 
         // Handle Uploads (simplified)
         if matches!(request.method, Method::POST) && route.allow_uploads.unwrap_or(false) {
-            return self.handle_upload(request, route);
+            return self.handle_upload(request, route, server_cfg);
+        }
+
+        if matches!(request.method, Method::PUT) && route.allow_uploads.unwrap_or(false) {
+            return self.handle_put(request, route, server_cfg);
         }
 
         // Handle DELETE
@@ -73,73 +246,254 @@ impl Router {
             return self.handle_delete(request, route, server_cfg);
         }
 
+        // A route may list an extension method for its CGI scripts; nothing else implements one
+        if matches!(request.method, Method::OTHER(_)) {
+            return generate_error_response(501, server_cfg, request);
+        }
+
         // Static file serving
         if let Some(root) = &route.root {
-            let mut path = PathBuf::from(root);
-            let relative_path = request.path.strip_prefix(&route.path).unwrap_or(&request.path);
-            path.push(relative_path.trim_start_matches('/'));
+            let mut path = match self.resolve_path(route, request, &request.path, root) {
+                Some(p) => p,
+                None => return generate_error_response(403, server_cfg, request),
+            };
 
             if path.is_dir() {
                 if let Some(index) = &route.index {
                     path.push(index);
-                } else if route.autoindex.unwrap_or(false) {
-                    return self.list_directory(&path);
+                } else if route.autoindex.unwrap_or(false) || has_autoindex_marker(&path, route) {
+                    return self.list_directory(&path, route, request);
+                } else {
+                    return generate_error_response(403, server_cfg, request);
+                }
+            }
+
+            let sidecars = route.header_sidecars.unwrap_or(false);
+            if sidecars && path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.ends_with(SIDECAR_SUFFIX)) {
+                return generate_error_response(404, server_cfg, request);
+            }
+
+            // `/data` may be served from `data.json` or `data.xml` depending on Accept
+            let negotiate = route.negotiate.unwrap_or(false);
+            if negotiate && path.extension().is_none() && !path.exists() {
+                if let Some(variant) = negotiate_variant(&path, request, &self.mime_overrides) {
+                    path = variant;
                 }
             }
 
-            match fs::read(&path) {
+            // Prefer a precompressed sibling (`app.js.br`) when the client accepts it
+            let brotli_static = route.brotli_static.unwrap_or(false);
+            let mut file_path = path.clone();
+            let mut encoding = None;
+            if brotli_static && accepts_encoding(request, "br") {
+                let mut br_path = path.clone().into_os_string();
+                br_path.push(".br");
+                let br_path = PathBuf::from(br_path);
+                if br_path.is_file() {
+                    file_path = br_path;
+                    encoding = Some("br");
+                }
+            }
+
+            // The index, variant or `.br` sibling is a different file from the one
+            // `resolve_path` checked, and may itself be a symlink out of the root
+            let root = route_root(route, request, root).unwrap_or_default();
+            if file_path.exists() && !self.within_root(&root, &file_path) {
+                return generate_error_response(403, server_cfg, request);
+            }
+            match fs::read(&file_path) {
                 Ok(content) => {
                     let mut res = Response::new(200);
                     res.body = content;
+                    if let Some(encoding) = encoding {
+                        res.headers.insert("Content-Encoding".to_string(), encoding.to_string());
+                    }
+                    let mut vary = Vec::new();
+                    if negotiate {
+                        vary.push("Accept");
+                    }
+                    if brotli_static {
+                        vary.push("Accept-Encoding");
+                    }
+                    if !vary.is_empty() {
+                        res.headers.insert("Vary".to_string(), vary.join(", "));
+                    }
                     if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
-                        let mime = match ext {
-                            "html" => "text/html",
-                            "css" => "text/css",
-                            "js" => "application/javascript",
-                            "png" => "image/png",
-                            _ => "application/octet-stream",
-                        };
-                        res.headers.insert("Content-Type".to_string(), mime.to_string());
+                        res.headers.insert("Content-Type".to_string(), mime_for_extension(ext, &self.mime_overrides).to_string());
+                    }
+                    if let Some(value) = cache_control_for(route, &path) {
+                        res.headers.insert("Cache-Control".to_string(), value.clone());
+                    }
+                    if sidecars {
+                        apply_sidecar_headers(&mut res, &path);
                     }
-                    res.headers.insert("Content-Length".to_string(), res.body.len().to_string());
                     return res;
                 }
-                Err(_) => return generate_error_response(404, server_cfg),
+                Err(_) => {
+                    let fallback = find_fallback(&path, route, request).filter(|f| self.within_root(&root, f));
+                    if let Some(fallback) = fallback {
+                        if let Ok(content) = fs::read(&fallback) {
+                            let mut res = Response::new(route.not_found_fallback_status.unwrap_or(200));
+                            res.body = content;
+                            if let Some(ext) = fallback.extension().and_then(|e| e.to_str()) {
+                                res.headers.insert("Content-Type".to_string(), mime_for_extension(ext, &self.mime_overrides).to_string());
+                            }
+                            return res;
+                        }
+                    }
+                    return generate_error_response(404, server_cfg, request);
+                }
+            }
+        }
+
+        generate_error_response(404, server_cfg, request)
+    }
+
+    fn handle_options(&self, route: &RouteConfig, server_cfg: &ServerConfig) -> Response {
+        let mut allow = allowed_methods(route, server_cfg);
+        if !allow.iter().any(|m| m == "OPTIONS") {
+            allow.push("OPTIONS".to_string());
+        }
+
+        let mut res = Response::new(204);
+        if allow.iter().any(|m| m == "PATCH") {
+            let types = route.accept_patch.clone()
+                .unwrap_or_else(|| vec![DEFAULT_ACCEPT_PATCH.to_string()]);
+            res.headers.insert("Accept-Patch".to_string(), types.join(", "));
+        }
+        res.headers.insert("Allow".to_string(), allow.join(", "));
+        res
+    }
+
+    fn handle_unmatched_options(&self, request: &Request, server_cfg: &ServerConfig) -> Response {
+        let mode = server_cfg.options_unmatched.unwrap_or(OptionsUnmatched::NotFound);
+        if mode == OptionsUnmatched::NotFound {
+            return generate_error_response(404, server_cfg, request);
+        }
+
+        // Union of every route's methods, in first-seen order
+        let mut allow: Vec<String> = Vec::new();
+        for route in &server_cfg.routes {
+            for method in allowed_methods(route, server_cfg) {
+                if !allow.contains(&method) {
+                    allow.push(method);
+                }
             }
         }
+        if !allow.iter().any(|m| m == "OPTIONS") {
+            allow.push("OPTIONS".to_string());
+        }
 
-        generate_error_response(404, server_cfg)
+        let mut res = match mode {
+            OptionsUnmatched::MethodNotAllowed => generate_error_response(405, server_cfg, request),
+            _ => Response::new(204),
+        };
+        res.headers.insert("Allow".to_string(), allow.join(", "));
+        res
     }
 
-    fn handle_cgi(&self, request: &Request, script_path: &str, interpreter: &str) -> Response {
-        let handler = CgiHandler::new(script_path.to_string(), interpreter.to_string());
-        let mut env_vars = HashMap::new();
-        env_vars.insert("REQUEST_METHOD".to_string(), format!("{:?}", request.method));
-        env_vars.insert("PATH_INFO".to_string(), request.path.clone());
+    #[allow(clippy::too_many_arguments)]
+    fn handle_cgi(
+        &self,
+        request: &Request,
+        route: &RouteConfig,
+        server_cfg: &ServerConfig,
+        script_path: &Path,
+        script_name: &str,
+        path_info: &str,
+        interpreter: &str,
+    ) -> Response {
+        let mut handler = CgiHandler::new(script_path.to_string_lossy().into_owned(), interpreter.to_string());
+        handler.max_output = route.cgi_max_output;
+        // Route-specific variables go in first so the protocol variables below take precedence
+        let mut env_vars = route.cgi_env.clone().unwrap_or_default();
+        env_vars.insert("REQUEST_METHOD".to_string(), request.method.as_str().to_string());
+        env_vars.insert("SCRIPT_NAME".to_string(), script_name.to_string());
+        env_vars.insert("PATH_INFO".to_string(), path_info.to_string());
+        if !path_info.is_empty() {
+            if let Some(root) = route_root(route, request, ".") {
+                let translated = Path::new(&root).join(path_info.trim_start_matches('/'));
+                env_vars.insert("PATH_TRANSLATED".to_string(), translated.to_string_lossy().into_owned());
+            }
+        }
+        let server_name = request.headers.get("Host")
+            .map(|h| h.rsplit_once(':').filter(|(_, port)| !port.contains(']')).map_or(h.as_str(), |(name, _)| name))
+            .unwrap_or_else(|| server_cfg.advertised_host());
+        env_vars.insert("SERVER_NAME".to_string(), server_name.to_string());
+        env_vars.insert("QUERY_STRING".to_string(), request.query.clone().unwrap_or_default());
         if let Some(len) = request.headers.get("Content-Length") {
             env_vars.insert("CONTENT_LENGTH".to_string(), len.clone());
         }
+        if let Some(content_type) = request.headers.get("Content-Type") {
+            env_vars.insert("CONTENT_TYPE".to_string(), content_type.clone());
+        }
+        forward_cgi_headers(request, route, &mut env_vars);
 
         match handler.execute(env_vars, &request.body) {
             Ok(output) => {
+                let Some((headers, body)) = cgi::split_output(&output) else {
+                    let mut res = Response::new(200);
+                    res.body = output;
+                    return res;
+                };
+
+                let max_headers = self.config.max_response_headers.unwrap_or(DEFAULT_MAX_RESPONSE_HEADERS);
+                let max_bytes = self.config.max_response_header_bytes.unwrap_or(DEFAULT_MAX_RESPONSE_HEADER_BYTES);
+                let bytes: usize = headers.iter().map(|(name, value)| name.len() + value.len() + 4).sum();
+                if headers.len() > max_headers || bytes > max_bytes {
+                    log::warn!("CGI {} sent {} response headers ({} bytes), over the limit", script_name, headers.len(), bytes);
+                    let mut res = Response::new(502);
+                    res.body = b"CGI Error: response headers too large".to_vec();
+                    return res;
+                }
+
                 let mut res = Response::new(200);
-                res.body = output;
-                res.headers.insert("Content-Length".to_string(), res.body.len().to_string());
+                let mut status = None;
+                for (name, value) in headers {
+                    if name.eq_ignore_ascii_case("Status") {
+                        status = value.get(..3).and_then(|code| code.parse::<u16>().ok());
+                    } else if !["Content-Length", "Connection", "Transfer-Encoding"]
+                        .iter().any(|h| name.eq_ignore_ascii_case(h))
+                    {
+                        res.headers.insert(name, value);
+                    }
+                }
+                // RFC 3875 6.2.3: a Location without Status is a redirect
+                res.status_code = status.unwrap_or(if res.headers.contains_key("Location") { 302 } else { 200 });
+                res.body = body.to_vec();
                 res
             }
             Err(e) => {
-                let mut res = Response::new(500);
+                let status = match e {
+                    CgiError::OutputTooLarge(_) => 502,
+                    _ => 500,
+                };
+                let mut res = Response::new(status);
                 res.body = format!("CGI Error: {}", e).into_bytes();
                 res
             }
         }
     }
 
-    fn handle_upload(&self, request: &Request, route: &RouteConfig) -> Response {
+    fn handle_upload(&self, request: &Request, route: &RouteConfig, server_cfg: &ServerConfig) -> Response {
         // In a real server, we'd parse multipart/form-data. 
         // For simplicity, we'll save the whole body as a file if a filename header is present or use a default.
-        let filename = request.headers.get("X-Filename").cloned().unwrap_or_else(|| "uploaded_file".to_string());
-        let mut path = PathBuf::from(route.root.as_deref().unwrap_or("static/uploads"));
+        if let Some(content_type) = request.headers.get("Content-Type") {
+            if content_type.trim_start().to_ascii_lowercase().starts_with("multipart/")
+                && multipart_boundary(content_type).is_none()
+            {
+                return generate_error_response(400, server_cfg, request);
+            }
+        }
+        let filename = request.headers.get("X-Filename")
+            .and_then(|f| Path::new(f).file_name())
+            .map(|f| f.to_os_string())
+            .unwrap_or_else(|| "uploaded_file".into());
+        let root = match route_root(route, request, "static/uploads") {
+            Some(r) => r,
+            None => return generate_error_response(403, server_cfg, request),
+        };
+        let mut path = PathBuf::from(root);
         path.push(filename);
 
         if let Some(parent) = path.parent() {
@@ -160,43 +514,521 @@ impl Router {
         }
     }
 
+    fn handle_put(&self, request: &Request, route: &RouteConfig, server_cfg: &ServerConfig) -> Response {
+        let path = match self.resolve_path(route, request, &request.path, "static/uploads") {
+            Some(p) => p,
+            None => return generate_error_response(403, server_cfg, request),
+        };
+
+        // A Content-Range places the body at an offset so uploads can be resumed
+        let offset = match request.headers.get("Content-Range") {
+            Some(range) => match parse_content_range(range) {
+                Some((start, end, total)) if end - start + 1 == request.body.len() as u64 => {
+                    if server_cfg.client_max_body_size.is_some_and(|max| total > max as u64) {
+                        return generate_error_response(413, server_cfg, request);
+                    }
+                    // Resuming past the end would leave a hole in the file
+                    let current = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+                    if start > current {
+                        let mut res = generate_error_response(416, server_cfg, request);
+                        res.headers.insert("Content-Range".to_string(), format!("bytes */{}", current));
+                        return res;
+                    }
+                    Some(start)
+                }
+                _ => return generate_error_response(400, server_cfg, request),
+            },
+            None => None,
+        };
+
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let created = !path.exists();
+
+        let result = match offset {
+            Some(start) => fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+                .and_then(|mut file| {
+                    file.seek(SeekFrom::Start(start))?;
+                    file.write_all(&request.body)
+                }),
+            None => fs::write(&path, &request.body),
+        };
+
+        match result {
+            Ok(_) if created => Response::new(201),
+            Ok(_) => Response::new(204),
+            Err(_) => generate_error_response(500, server_cfg, request),
+        }
+    }
+
     fn handle_delete(&self, request: &Request, route: &RouteConfig, server_cfg: &ServerConfig) -> Response {
-        let mut path = PathBuf::from(route.root.as_deref().unwrap_or("."));
-        let relative_path = request.path.strip_prefix(&route.path).unwrap_or(&request.path);
-        path.push(relative_path.trim_start_matches('/'));
+        let path = match self.resolve_path(route, request, &request.path, ".") {
+            Some(p) => p,
+            None => return generate_error_response(403, server_cfg, request),
+        };
 
         if path.exists() && path.is_file() {
             match fs::remove_file(path) {
                 Ok(_) => Response::new(204),
-                Err(_) => generate_error_response(500, server_cfg),
+                Err(_) => generate_error_response(500, server_cfg, request),
             }
         } else {
-            generate_error_response(404, server_cfg)
+            generate_error_response(404, server_cfg, request)
         }
     }
 
-    fn find_route<'a>(&self, server: &'a crate::config::ServerConfig, path: &str) -> Option<&'a RouteConfig> {
-        // Longest prefix match
-        server.routes.iter()
-            .filter(|r| path.starts_with(&r.path))
-            .max_by_key(|r| r.path.len())
+    // Maps the request path onto the route's root, refusing anything that escapes it
+    fn resolve_path(&self, route: &RouteConfig, request: &Request, request_path: &str, default_root: &str) -> Option<PathBuf> {
+        let root = route_root(route, request, default_root)?;
+        let relative = request_path.strip_prefix(&route.path)
+            .or_else(|| request_path.strip_prefix(route.path.trim_end_matches('/')))
+            .unwrap_or(request_path);
+        let relative = Path::new(relative.trim_start_matches('/'));
+        if relative.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+            return None;
+        }
+
+        let path = Path::new(&root).join(relative);
+        self.within_root(&root, &path).then_some(path)
+    }
+
+    // Symlinks can still point outside the root, so compare canonical forms. Roots
+    // that are per-host or didn't exist at startup are resolved on demand.
+    fn within_root(&self, root: &str, path: &Path) -> bool {
+        let canonical_root = match self.canonical_roots.get(root) {
+            Some(c) => c.clone(),
+            None => match canonicalize_existing(Path::new(root)) {
+                Some(c) => c,
+                None => return false,
+            },
+        };
+        // The target may not exist yet (PUT, uploads), but a symlinked directory on the
+        // way to it still has to stay under the root
+        canonicalize_existing(path).is_some_and(|p| p.starts_with(canonical_root))
+    }
+
+    pub fn find_route(&self, server_idx: usize, path: &str) -> Option<&RouteConfig> {
+        // Longest prefix match: probe each prefix from the longest down, so the cost
+        // depends on the path length rather than the number of routes
+        let routes = &self.route_maps[server_idx];
+        let strict = self.config.servers[server_idx].strict_slash.unwrap_or(true);
+        let path = route_key(path, strict);
+        let path = path.as_str();
+        (0..=path.len()).rev()
+            .filter(|&end| path.is_char_boundary(end))
+            .find_map(|end| routes.get(&path[..end]))
+            .map(|&i| &self.config.servers[server_idx].routes[i])
     }
 
-    fn list_directory(&self, path: &PathBuf) -> Response {
-        let mut html = String::from("<html><body><ul>");
-        if let Ok(entries) = fs::read_dir(path) {
-            for entry in entries.flatten() {
+    fn list_directory(&self, path: &PathBuf, route: &RouteConfig, request: &Request) -> Response {
+        // The directory's mtime changes when entries are added or removed
+        let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+        if let Some(modified) = modified {
+            let since = request.headers.get("If-Modified-Since").and_then(|v| parse_http_date(v));
+            let secs = |t: SystemTime| t.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+            if since.is_some_and(|since| secs(modified) <= secs(since)) {
+                let mut res = Response::new(304);
+                res.headers.insert("Last-Modified".to_string(), http_date(modified));
+                return res;
+            }
+        }
+        let request_path = request.path.as_str();
+        let query = request.query.as_deref();
+        let mut entries: Vec<(String, u64, SystemTime)> = Vec::new();
+        if let Ok(dir) = fs::read_dir(path) {
+            for entry in dir.flatten() {
                 if let Ok(name) = entry.file_name().into_string() {
-                    html.push_str(&format!("<li><a href=\"{}\">{}</a></li>", name, name));
+                    if route.autoindex_marker.as_ref() == Some(&name) {
+                        continue;
+                    }
+                    let meta = entry.metadata().ok();
+                    let size = meta.as_ref().map(|m| m.len()).unwrap_or(0);
+                    let modified = meta.and_then(|m| m.modified().ok()).unwrap_or(UNIX_EPOCH);
+                    entries.push((name, size, modified));
                 }
             }
         }
-        html.push_str("</ul></body></html>");
+
+        let sort = query.and_then(sort_from_query)
+            .or(route.autoindex_sort)
+            .unwrap_or(AutoindexSort::NameAsc);
+        match sort {
+            AutoindexSort::NameAsc => entries.sort_by(|a, b| a.0.cmp(&b.0)),
+            AutoindexSort::NameDesc => entries.sort_by(|a, b| b.0.cmp(&a.0)),
+            AutoindexSort::SizeAsc => entries.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.cmp(&b.0))),
+            AutoindexSort::SizeDesc => entries.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0))),
+            AutoindexSort::DateAsc => entries.sort_by(|a, b| a.2.cmp(&b.2).then_with(|| a.0.cmp(&b.0))),
+            AutoindexSort::DateDesc => entries.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.0.cmp(&b.0))),
+        }
+
+        let mut html = template::render(AUTOINDEX_HEADER, &[("path", request_path), ("server", SERVER_TOKEN)]);
+        for (name, _, _) in &entries {
+            let name = html_escape(name);
+            html.push_str(&format!("<li><a href=\"{}\">{}</a></li>", name, name));
+        }
+        html.push_str(&template::render(AUTOINDEX_FOOTER, &[("path", request_path), ("server", SERVER_TOKEN)]));
         
         let mut res = Response::new(200);
         res.body = html.into_bytes();
         res.headers.insert("Content-Type".to_string(), "text/html".to_string());
-        res.headers.insert("Content-Length".to_string(), res.body.len().to_string());
+        if let Some(modified) = modified {
+            res.headers.insert("Last-Modified".to_string(), http_date(modified));
+        }
         res
     }
 }
+
+// Resolves a relative redirect target against the request's host and path
+fn absolute_location(target: &str, request: &Request, server_cfg: &ServerConfig) -> String {
+    if target.contains("://") {
+        return target.to_string();
+    }
+    let host = request.headers.get("Host").cloned().unwrap_or_else(|| server_cfg.advertised_host().to_string());
+    let path = if target.starts_with('/') {
+        target.to_string()
+    } else {
+        let dir = request.path.rsplit_once('/').map_or("", |(dir, _)| dir);
+        format!("{}/{}", dir, target)
+    };
+    format!("http://{}{}", host, path)
+}
+
+fn allowed_methods(route: &RouteConfig, server_cfg: &ServerConfig) -> Vec<String> {
+    route.methods.clone().unwrap_or_else(|| default_methods(server_cfg))
+}
+
+fn default_methods(server_cfg: &ServerConfig) -> Vec<String> {
+    server_cfg.default_methods.clone()
+        .unwrap_or_else(|| DEFAULT_ALLOW.iter().map(|m| m.to_string()).collect())
+}
+
+// Adds request headers as `HTTP_*` variables, stopping at the route's count and size caps
+fn forward_cgi_headers(request: &Request, route: &RouteConfig, env_vars: &mut HashMap<String, String>) {
+    let max_headers = route.cgi_max_headers.unwrap_or(DEFAULT_CGI_MAX_HEADERS);
+    let max_bytes = route.cgi_max_header_bytes.unwrap_or(DEFAULT_CGI_MAX_HEADER_BYTES);
+    let mut names: Vec<&String> = request.headers.keys().collect();
+    names.sort();
+
+    let (mut forwarded, mut bytes, mut dropped) = (0, 0, 0);
+    for name in names {
+        // Already exposed as CONTENT_*; `Proxy` would become HTTP_PROXY (httpoxy)
+        if name.eq_ignore_ascii_case("Content-Length")
+            || name.eq_ignore_ascii_case("Content-Type")
+            || name.eq_ignore_ascii_case("Proxy")
+        {
+            continue;
+        }
+        let key = format!("HTTP_{}", name.to_ascii_uppercase().replace('-', "_"));
+        let value = &request.headers[name];
+        let size = key.len() + value.len();
+        if forwarded >= max_headers || bytes + size > max_bytes {
+            dropped += 1;
+            continue;
+        }
+        forwarded += 1;
+        bytes += size;
+        env_vars.insert(key, value.clone());
+    }
+    if dropped > 0 {
+        log::warn!("dropped {} request headers exceeding the CGI header limits for {}", dropped, request.path);
+    }
+}
+
+// Directory-wide `.headers` first, then the file's own sidecar so it can override.
+// A `Status` line replaces the response status.
+fn apply_sidecar_headers(res: &mut Response, path: &Path) {
+    let mut sidecars = Vec::new();
+    if let Some(dir) = path.parent() {
+        sidecars.push(dir.join(SIDECAR_SUFFIX));
+    }
+    let mut own = path.as_os_str().to_os_string();
+    own.push(SIDECAR_SUFFIX);
+    sidecars.push(PathBuf::from(own));
+
+    for sidecar in sidecars {
+        let Ok(content) = fs::read_to_string(&sidecar) else { continue };
+        for line in content.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((name, value)) = line.split_once(':') else { continue };
+            let (name, value) = (name.trim(), value.trim());
+            if name.eq_ignore_ascii_case("Status") {
+                if let Ok(status) = value.parse() {
+                    res.status_code = status;
+                }
+            } else if !name.is_empty() && !name.eq_ignore_ascii_case("Content-Length") {
+                res.headers.insert(name.to_string(), value.to_string());
+            }
+        }
+    }
+}
+
+// Nearest `not_found_fallback` file between the missing path's directory and the route root
+fn find_fallback(missing: &Path, route: &RouteConfig, request: &Request) -> Option<PathBuf> {
+    let name = route.not_found_fallback.as_ref()?;
+    let root = PathBuf::from(route_root(route, request, ".")?);
+    let mut dir = missing.parent();
+    while let Some(current) = dir.filter(|d| d.starts_with(&root)) {
+        let candidate = current.join(name);
+        if candidate.is_file() {
+            return Some(candidate);
+        }
+        dir = current.parent();
+    }
+    None
+}
+
+fn has_autoindex_marker(dir: &Path, route: &RouteConfig) -> bool {
+    route.autoindex_marker.as_ref().is_some_and(|marker| dir.join(marker).is_file())
+}
+
+// RFC 2046 5.1.1: 1-70 characters from `bchars`, not ending in a space
+fn multipart_boundary(content_type: &str) -> Option<&str> {
+    let boundary = content_type.split(';').skip(1).find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim().eq_ignore_ascii_case("boundary").then(|| value.trim())
+    })?;
+    let boundary = boundary.strip_prefix('"').and_then(|b| b.strip_suffix('"')).unwrap_or(boundary);
+    let valid_char = |c: char| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c);
+    if boundary.is_empty() || boundary.len() > 70 || boundary.ends_with(' ') || !boundary.chars().all(valid_char) {
+        return None;
+    }
+    Some(boundary)
+}
+
+fn https_location(host: &str, request: &Request, server_cfg: &ServerConfig) -> String {
+    let host = if host.is_empty() { server_cfg.advertised_host() } else { host };
+    // Drop the plaintext port, keeping IPv6 brackets intact
+    let hostname = match host.rfind(':') {
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    };
+    let port = match server_cfg.https_port {
+        Some(port) if port != 443 => format!(":{}", port),
+        _ => String::new(),
+    };
+    let query = request.query.as_ref().map_or(String::new(), |q| format!("?{}", q));
+    format!("https://{}{}{}{}", hostname, port, request.path, query)
+}
+
+// Canonicalizes the longest existing prefix of `path` and appends the rest as-is.
+// None for a dangling symlink, whose eventual target can't be checked.
+fn canonicalize_existing(path: &Path) -> Option<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        let probe = if existing.as_os_str().is_empty() { Path::new(".") } else { existing };
+        match fs::canonicalize(probe) {
+            Ok(canonical) => return Some(missing.iter().rev().fold(canonical, |acc, name| acc.join(name))),
+            Err(_) if fs::symlink_metadata(probe).is_ok() => return None,
+            Err(_) => {
+                missing.push(existing.file_name()?);
+                existing = existing.parent()?;
+            }
+        }
+    }
+}
+
+// Without strict_slash, `/api` and `/api/` share the same lookup key
+fn route_key(path: &str, strict: bool) -> String {
+    if strict || path == "/" {
+        return path.to_string();
+    }
+    let trimmed = path.trim_end_matches('/');
+    if trimmed.is_empty() { "/".to_string() } else { trimmed.to_string() }
+}
+
+// Accepts `host[:port]` where host is a reg-name or bracketed IPv6 literal;
+// userinfo, paths and other URL parts are rejected
+fn valid_host(value: &str) -> bool {
+    let port_ok = |port: Option<&str>| {
+        port.is_none_or(|p| !p.is_empty() && p.len() <= 5 && p.chars().all(|c| c.is_ascii_digit()))
+    };
+    if let Some(rest) = value.strip_prefix('[') {
+        return match rest.split_once(']') {
+            Some((addr, port)) => {
+                !addr.is_empty()
+                    && addr.chars().all(|c| c.is_ascii_hexdigit() || c == ':' || c == '.')
+                    && (port.is_empty() || port.strip_prefix(':').is_some_and(|p| port_ok(Some(p))))
+            }
+            None => false,
+        };
+    }
+    let (name, port) = match value.rsplit_once(':') {
+        Some((name, port)) => (name, Some(port)),
+        None => (value, None),
+    };
+    !name.is_empty() && reg_name(name) && port_ok(port)
+}
+
+// RFC 3986 reg-name: unreserved characters, sub-delims and %XX escapes
+fn reg_name(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if bytes.get(i + 1..i + 3).is_some_and(|hex| hex.iter().all(u8::is_ascii_hexdigit)) => i += 3,
+            b if b.is_ascii_alphanumeric() || b"-._~!$&'()*+,;=".contains(&b) => i += 1,
+            _ => return false,
+        }
+    }
+    true
+}
+
+// Expands `$host` in the route root from the Host header, rejecting values
+// that could escape the templated directory
+fn route_root(route: &RouteConfig, request: &Request, default_root: &str) -> Option<String> {
+    let root = route.root.as_deref().unwrap_or(default_root);
+    if !root.contains(HOST_VAR) {
+        return Some(root.to_string());
+    }
+    let host = request.headers.get("Host")?;
+    let host = host.rsplit_once(':').map_or(host.as_str(), |(name, _)| name).to_ascii_lowercase();
+    let valid = !host.is_empty()
+        && !host.starts_with('.')
+        && !host.contains("..")
+        && host.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if !valid {
+        return None;
+    }
+    Some(root.replace(HOST_VAR, &host))
+}
+
+// Splits `/app.py/extra/path` at the first segment with a CGI extension into
+// SCRIPT_NAME (`/app.py`) and PATH_INFO (`/extra/path`)
+fn split_script_path<'a>(path: &'a str, cgi_exts: &'a HashMap<String, String>) -> Option<(&'a str, &'a str, &'a String)> {
+    let mut end = 0;
+    for segment in path.split('/') {
+        end += segment.len();
+        if let Some(interpreter) = segment.rfind('.').and_then(|dot| cgi_exts.get(&segment[dot..])) {
+            return Some((&path[..end], &path[end..], interpreter));
+        }
+        end += 1;
+    }
+    None
+}
+
+// Apache-style `C=N|S|M;O=A|D` query parameters for autoindex ordering
+fn sort_from_query(query: &str) -> Option<AutoindexSort> {
+    let mut column = None;
+    let mut descending = false;
+    for pair in query.split([';', '&']) {
+        match pair.split_once('=') {
+            Some(("C", c)) => column = Some(c),
+            Some(("O", o)) => descending = o == "D",
+            _ => {}
+        }
+    }
+    let sort = match (column?, descending) {
+        ("N", false) => AutoindexSort::NameAsc,
+        ("N", true) => AutoindexSort::NameDesc,
+        ("S", false) => AutoindexSort::SizeAsc,
+        ("S", true) => AutoindexSort::SizeDesc,
+        ("M", false) => AutoindexSort::DateAsc,
+        ("M", true) => AutoindexSort::DateDesc,
+        _ => return None,
+    };
+    Some(sort)
+}
+
+fn mime_for_extension<'a>(ext: &str, overrides: &'a HashMap<String, String>) -> &'a str {
+    known_mime(ext, overrides).unwrap_or("application/octet-stream")
+}
+
+// Configured overrides win over the built-in table; extensions match case-insensitively
+fn known_mime<'a>(ext: &str, overrides: &'a HashMap<String, String>) -> Option<&'a str> {
+    let ext = ext.to_ascii_lowercase();
+    if let Some(mime) = overrides.get(&ext) {
+        return Some(mime);
+    }
+    let mime = match ext.as_str() {
+        "html" => "text/html",
+        "css" => "text/css",
+        "js" => "application/javascript",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "txt" => "text/plain",
+        _ => return None,
+    };
+    Some(mime)
+}
+
+// Picks the sibling `name.<ext>` whose media type the client rates highest
+fn negotiate_variant(path: &Path, request: &Request, mime_overrides: &HashMap<String, String>) -> Option<PathBuf> {
+    let stem = path.file_name()?.to_str()?;
+    let dir = path.parent()?;
+    let accept = request.headers.get("Accept").map(String::as_str).unwrap_or("*/*");
+
+    let mut variants: Vec<PathBuf> = fs::read_dir(dir).ok()?
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.file_stem().and_then(|s| s.to_str()) == Some(stem))
+        .collect();
+    // Deterministic tie-breaking between equally acceptable variants
+    variants.sort();
+
+    let mut best: Option<(f32, PathBuf)> = None;
+    for variant in variants {
+        // Header sidecars and precompressed copies aren't representations of their own,
+        // and neither is a file whose media type can't be named
+        let Some(name) = variant.file_name().and_then(|n| n.to_str()) else { continue };
+        if name.ends_with(SIDECAR_SUFFIX) || name.ends_with(".br") {
+            continue;
+        }
+        let Some(mime) = variant.extension().and_then(|e| e.to_str()).and_then(|ext| known_mime(ext, mime_overrides)) else {
+            continue;
+        };
+        let q = media_quality(accept, mime);
+        if q > 0.0 && best.as_ref().is_none_or(|(best_q, _)| q > *best_q) {
+            best = Some((q, variant));
+        }
+    }
+    best.map(|(_, p)| p)
+}
+
+// Looks up `cache_control` by the file's extension (e.g. `.js`), falling back to `*`
+fn cache_control_for<'a>(route: &'a RouteConfig, path: &Path) -> Option<&'a String> {
+    let rules = route.cache_control.as_ref()?;
+    path.extension()
+        .and_then(|e| e.to_str())
+        .and_then(|ext| rules.get(&format!(".{}", ext)))
+        .or_else(|| rules.get("*"))
+}
+
+// An explicit entry for the coding wins over `*`; q=0 means not acceptable
+fn accepts_encoding(request: &Request, coding: &str) -> bool {
+    let header = match request.headers.get("Accept-Encoding") {
+        Some(h) => h,
+        None => return false,
+    };
+    let prefs = parse_accept(header);
+    let q = prefs.iter()
+        .find(|(token, _)| token.eq_ignore_ascii_case(coding))
+        .or_else(|| prefs.iter().find(|(token, _)| token == "*"))
+        .map_or(0.0, |(_, q)| *q);
+    q > 0.0
+}
+
+// Parses `bytes start-end/total` into an inclusive byte range and the complete length
+fn parse_content_range(value: &str) -> Option<(u64, u64, u64)> {
+    let spec = value.trim().strip_prefix("bytes ")?;
+    let (range, total) = spec.split_once('/')?;
+    let (start, end) = range.split_once('-')?;
+    let start = start.trim().parse::<u64>().ok()?;
+    let end = end.trim().parse::<u64>().ok()?;
+    // `*` is refused: the complete length is what gets checked against the size limit
+    let total = total.trim().parse::<u64>().ok()?;
+    if end < start || end >= total {
+        return None;
+    }
+    Some((start, end, total))
+}
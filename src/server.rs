@@ -1,152 +1,569 @@
-use crate::config::Config;
+use crate::access_log::{self, AccessEntry};
+use crate::config::{CloseOnError, Config, LogFormat, RetryAfter};
 use mio::net::{TcpListener, TcpStream};
 use mio::{Events, Interest, Poll, Token};
 use std::collections::HashMap;
 use std::io::{self, Read, Write};
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
 
-use crate::http::{Parser, ParseState, Response};
+use crate::http::{Parser, ParseState, Request, Response, CLOSE_WITHOUT_RESPONSE};
 use crate::router::Router;
 use std::time::{Duration, Instant};
 
 const TIMEOUT: Duration = Duration::from_secs(30);
+// Connections that never send a byte are dropped sooner than idle keep-alive ones
+const INITIAL_TIMEOUT: Duration = Duration::from_secs(5);
+const SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+// Consecutive zero-length writes tolerated before the peer is considered stuck
+const MAX_ZERO_WRITES: u8 = 2;
+const DEFAULT_EVENT_CAPACITY: usize = 1024;
+const ACCEPT_WINDOW: Duration = Duration::from_secs(1);
+// Minimum gap between soft connection cap warnings
+const SOFT_CAP_WARN_INTERVAL: Duration = Duration::from_secs(60);
 
 pub struct Server {
     poll: Poll,
     listeners: Vec<(TcpListener, Token)>,
+    // Server block index for each listener, so per-listener settings reach the router
+    listener_servers: Vec<usize>,
+    listener_tokens: HashMap<Token, usize>,
+    event_capacity: usize,
     connections: HashMap<Token, Connection>,
     next_token: usize,
     router: Router,
+    log_format: Option<LogFormat>,
+    log_sample_rate: f64,
+    next_request_id: u64,
+    max_accepts_per_sec: Option<u32>,
+    accept_window_start: Instant,
+    accepts_in_window: u32,
+    deferred_listeners: Vec<usize>,
+    max_total_buffer: Option<usize>,
+    total_buffered: usize,
+    close_on_error: CloseOnError,
+    max_uri_length: Option<usize>,
+    lenient_methods: bool,
+    client_body_timeout: Option<Duration>,
+    soft_max_connections: Option<usize>,
+    max_connection_lifetime: Option<Duration>,
+    // Set while above the soft cap so each crossing warns only once
+    above_soft_cap: bool,
+    last_soft_cap_warning: Option<Instant>,
+    max_connections_per_ip: Option<usize>,
+    // Open connections per client, kept only while `max_connections_per_ip` is set
+    connections_per_ip: HashMap<IpAddr, usize>,
+    retry_after: Option<RetryAfter>,
+    log_bodies: Option<usize>,
+    status_path: Option<String>,
+    status_allow: Option<Vec<IpAddr>>,
+    explicit_keep_alive: bool,
 }
 
 struct Connection {
     socket: TcpStream,
+    peer: SocketAddr,
+    listener_server: usize,
     parser: Parser,
     response_buf: Vec<u8>,
     is_closing: bool,
     last_activity: Instant,
+    bytes_received: usize,
+    zero_writes: u8,
+    read_paused: bool,
+    // When the parser moved past the headers into the body, for `client_body_timeout`
+    body_started: Option<Instant>,
+    created: Instant,
+}
+
+impl Connection {
+    fn buffered(&self) -> usize {
+        self.parser.buffered() + self.response_buf.len()
+    }
 }
 
 impl Server {
     pub fn new(config: Config) -> io::Result<Self> {
         let poll = Poll::new()?;
         let mut listeners = Vec::new();
-        let next_token = 100; // Start high to avoid conflicts with listeners
-        
-        for server_cfg in &config.servers {
-            for port in &server_cfg.ports {
+        let mut listener_servers = Vec::new();
+        let mut listener_tokens = HashMap::new();
+        let mut banners = Vec::new();
+
+        // Every listener is bound before any is announced or accepted from. On a failed
+        // bind the ones already bound are dropped with the partial state, closing them.
+        for (server_idx, server_cfg) in config.servers.iter().enumerate() {
+            for port in server_cfg.listen_ports() {
                 let addr: SocketAddr = format!("{}:{}", server_cfg.host, port).parse()
                     .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
                 
-                let mut listener = TcpListener::bind(addr)?;
+                let mut listener = TcpListener::bind(addr)
+                    .map_err(|e| io::Error::new(e.kind(), format!("binding {}: {}", addr, e)))?;
                 let token = Token(listeners.len());
                 
                 poll.registry().register(&mut listener, token, Interest::READABLE)?;
+                listener_tokens.insert(token, listeners.len());
                 listeners.push((listener, token));
-                println!("Listening on {}", addr);
+                listener_servers.push(server_idx);
+                if server_cfg.advertised_host() != server_cfg.host {
+                    banners.push(format!("Listening on {} ({})", addr, server_cfg.advertised_host()));
+                } else {
+                    banners.push(format!("Listening on {}", addr));
+                }
             }
         }
+        for banner in banners {
+            println!("{}", banner);
+        }
 
         Ok(Server {
             poll,
+            // Connection tokens follow the listeners', however many there are
+            next_token: listeners.len(),
             listeners,
+            listener_servers,
+            listener_tokens,
+            event_capacity: config.event_capacity.unwrap_or(DEFAULT_EVENT_CAPACITY).max(1),
             connections: HashMap::new(),
-            next_token,
+            log_format: config.log_format.clone(),
+            log_sample_rate: config.log_sample_rate.unwrap_or(1.0),
+            next_request_id: 0,
+            max_accepts_per_sec: config.max_accepts_per_sec,
+            accept_window_start: Instant::now(),
+            accepts_in_window: 0,
+            deferred_listeners: Vec::new(),
+            max_total_buffer: config.max_total_buffer,
+            total_buffered: 0,
+            close_on_error: config.close_on_error.unwrap_or(CloseOnError::Never),
+            max_uri_length: config.max_uri_length,
+            lenient_methods: config.lenient_methods.unwrap_or(false),
+            client_body_timeout: config.client_body_timeout.map(Duration::from_secs),
+            soft_max_connections: config.soft_max_connections,
+            max_connection_lifetime: config.max_connection_lifetime.map(Duration::from_secs),
+            above_soft_cap: false,
+            last_soft_cap_warning: None,
+            max_connections_per_ip: config.max_connections_per_ip,
+            connections_per_ip: HashMap::new(),
+            retry_after: config.retry_after.clone(),
+            log_bodies: config.log_bodies,
+            status_path: config.status_path.clone(),
+            status_allow: config.status_allow.clone(),
+            explicit_keep_alive: config.explicit_keep_alive.unwrap_or(false),
             router: Router::new(config),
         })
     }
 
     pub fn run(&mut self) -> io::Result<()> {
-        let mut events = Events::with_capacity(1024);
+        let mut events = Events::with_capacity(self.event_capacity);
         let mut buffer = [0; 4096];
 
         loop {
-            self.poll.poll(&mut events, None)?;
+            // Wake up periodically to reap timed-out connections, and for the next accept
+            // window when listeners are waiting on the rate limit
+            let mut timeout = if self.connections.is_empty() { None } else { Some(SWEEP_INTERVAL) };
+            if !self.deferred_listeners.is_empty() {
+                let window_left = ACCEPT_WINDOW.saturating_sub(self.accept_window_start.elapsed());
+                timeout = Some(timeout.map_or(window_left, |t| t.min(window_left)));
+            }
+            self.poll.poll(&mut events, timeout)?;
+
+            if !self.deferred_listeners.is_empty() && self.accept_window_start.elapsed() >= ACCEPT_WINDOW {
+                for idx in std::mem::take(&mut self.deferred_listeners) {
+                    self.accept_connections(idx)?;
+                }
+            }
 
             for event in events.iter() {
                 let token = event.token();
 
-                if token.0 < self.listeners.len() {
-                    // New connection
-                    loop {
-                        match self.listeners[token.0].0.accept() {
-                            Ok((mut socket, _)) => {
-                                let conn_token = Token(self.next_token);
-                                self.next_token += 1;
-
-                                self.poll.registry().register(
-                                    &mut socket,
-                                    conn_token,
-                                    Interest::READABLE | Interest::WRITABLE,
-                                )?;
-
-                                self.connections.insert(conn_token, Connection { 
-                                    socket, 
-                                    parser: Parser::new(),
-                                    response_buf: Vec::new(),
-                                    is_closing: false,
-                                    last_activity: Instant::now(),
-                                });
-                            }
-                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                            Err(e) => return Err(e),
-                        }
-                    }
-                } else if let Some(connection) = self.connections.get_mut(&token) {
-                    connection.last_activity = Instant::now();
+                if let Some(&idx) = self.listener_tokens.get(&token) {
+                    self.accept_connections(idx)?;
+                } else if self.connections.contains_key(&token) {
                     if event.is_readable() {
-                        loop {
-                            match connection.socket.read(&mut buffer) {
-                                Ok(0) => {
-                                    connection.is_closing = true;
-                                    break;
-                                }
-                                Ok(n) => {
-                                    connection.parser.parse(&buffer[..n]);
-                                    if connection.parser.state == ParseState::Done {
-                                        let response = self.router.handle(&connection.parser.request);
-                                        connection.response_buf.extend_from_slice(&response.to_bytes());
-                                        // Reset parser for next request (keep-alive support could be here)
-                                        connection.parser = Parser::new();
-                                    } else if connection.parser.state == ParseState::Error {
-                                        let response = Response::new(400);
-                                        connection.response_buf.extend_from_slice(&response.to_bytes());
-                                        connection.is_closing = true;
-                                        break;
-                                    }
-                                }
-                                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
-                                Err(_) => {
-                                    connection.is_closing = true;
-                                    break;
-                                }
-                            }
-                        }
+                        self.read_connection(token, &mut buffer);
+                    }
+                    // Send a response produced by the read right away instead of waiting for
+                    // another writable edge; one write per event keeps other connections served
+                    if event.is_writable() || self.has_pending_output(token) {
+                        self.write_connection(token);
                     }
+                }
+            }
 
-                    if event.is_writable() && !connection.response_buf.is_empty() {
-                        match connection.socket.write(&connection.response_buf) {
-                            Ok(n) => {
-                                connection.response_buf.drain(..n);
-                            }
-                            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
-                            Err(_) => {
-                                connection.is_closing = true;
-                            }
-                        }
+            // Retry connections paused for backpressure; they pause again if still over the limit
+            let paused: Vec<Token> = self.connections.iter()
+                .filter(|(_, conn)| conn.read_paused)
+                .map(|(token, _)| *token)
+                .collect();
+            for token in paused {
+                self.read_connection(token, &mut buffer);
+            }
+
+            // Answer slow request bodies with 408; the write may not get another event
+            if let Some(limit) = self.client_body_timeout {
+                let expired: Vec<Token> = self.connections.iter()
+                    .filter(|(_, conn)| !conn.is_closing && conn.body_started.is_some_and(|t| t.elapsed() > limit))
+                    .map(|(token, _)| *token)
+                    .collect();
+                for token in expired {
+                    if let Some(conn) = self.connections.get_mut(&token) {
+                        let mut response = Response::new(408);
+                        response.headers.insert("Connection".to_string(), "close".to_string());
+                        let bytes = response.to_bytes();
+                        self.total_buffered += bytes.len();
+                        conn.response_buf.extend_from_slice(&bytes);
+                        conn.is_closing = true;
                     }
+                    self.write_connection(token);
                 }
             }
 
             // Cleanup closed or timed-out connections
             let now = Instant::now();
+            let total_buffered = &mut self.total_buffered;
+            let per_ip = &mut self.connections_per_ip;
+            let max_lifetime = self.max_connection_lifetime;
             self.connections.retain(|_, conn| {
-                if (conn.is_closing && conn.response_buf.is_empty()) || now.duration_since(conn.last_activity) > TIMEOUT {
-                    false
-                } else {
-                    true
+                let timeout = if conn.bytes_received == 0 { INITIAL_TIMEOUT } else { TIMEOUT };
+                // Past its lifetime, a connection is dropped once it sits between requests;
+                // one mid-request is closed after its response instead
+                let between_requests = conn.parser.buffered() == 0 && conn.response_buf.is_empty();
+                let expired = max_lifetime.is_some_and(|max| now.duration_since(conn.created) >= max) && between_requests;
+                let keep = !((conn.is_closing && conn.response_buf.is_empty())
+                    || expired
+                    || now.duration_since(conn.last_activity) > timeout);
+                if !keep {
+                    *total_buffered = total_buffered.saturating_sub(conn.buffered());
+                    release_ip(per_ip, conn.peer.ip());
                 }
+                keep
             });
+            if self.soft_max_connections.is_some_and(|soft| self.connections.len() <= soft) {
+                self.above_soft_cap = false;
+            }
+        }
+    }
+
+    fn has_pending_output(&self, token: Token) -> bool {
+        self.connections.get(&token).is_some_and(|conn| !conn.response_buf.is_empty())
+    }
+
+    fn over_buffer_limit(&self) -> bool {
+        self.max_total_buffer.is_some_and(|limit| self.total_buffered > limit)
+    }
+
+    fn read_connection(&mut self, token: Token, buffer: &mut [u8]) {
+        let over_limit = self.over_buffer_limit();
+        let fair_share = self.total_buffered / self.connections.len().max(1);
+        // Taken out of the map while reading so the status page can list the others
+        let mut connection = match self.connections.remove(&token) {
+            Some(c) => c,
+            None => return,
+        };
+        let before = connection.buffered();
+
+        if over_limit && before >= fair_share && !connection.response_buf.is_empty() {
+            // Stop reading from the heaviest connections until their pending output drains.
+            // A paused peer that never reads its output still times out.
+            connection.read_paused = true;
+            self.connections.insert(token, connection);
+            return;
+        }
+        connection.read_paused = false;
+        connection.last_activity = Instant::now();
+
+        'read: loop {
+            match connection.socket.read(buffer) {
+                Ok(0) => {
+                    connection.is_closing = true;
+                    break;
+                }
+                Ok(n) => {
+                    connection.bytes_received += n;
+                    connection.parser.parse(&buffer[..n]);
+                    // A single read may carry several pipelined requests
+                    while connection.parser.state == ParseState::Done {
+                        let started = Instant::now();
+                        let request = &connection.parser.request;
+                        if let Some(max) = self.log_bodies.filter(|_| !request.body.is_empty()) {
+                            if self.router.route_for(request).and_then(|r| r.sensitive).unwrap_or(false) {
+                                log::debug!("{} {} body: <redacted, {} bytes>", request.method.as_str(), request.path, request.body.len());
+                            } else {
+                                log::debug!("{} {} body: {}", request.method.as_str(), request.path, access_log::body_preview(&request.body, max));
+                            }
+                        }
+                        let mut response = if self.status_path.as_deref() == Some(request.path.as_str()) {
+                            self.status_response(token, &connection)
+                        } else {
+                            self.router.handle(request, connection.listener_server)
+                        };
+                        let dropped = response.status_code == CLOSE_WITHOUT_RESPONSE;
+                        let expired = self.max_connection_lifetime.is_some_and(|max| connection.created.elapsed() >= max);
+                        let close = dropped || expired || wants_close(request) || self.close_on_error.closes(response.status_code);
+                        // A blackholed request gets nothing written back
+                        if !dropped {
+                            if close {
+                                response.headers.insert("Connection".to_string(), "close".to_string());
+                            } else if self.explicit_keep_alive && request.version == "HTTP/1.1" {
+                                response.headers.insert("Connection".to_string(), "keep-alive".to_string());
+                            }
+                            connection.response_buf.extend_from_slice(&response.to_bytes());
+                        }
+
+                        self.next_request_id += 1;
+                        let format = self.log_format.as_ref()
+                            .filter(|_| access_log::sampled(self.next_request_id, self.log_sample_rate));
+                        if let Some(format) = format {
+                            let entry = AccessEntry {
+                                request_id: self.next_request_id,
+                                peer: connection.peer,
+                                host: request.headers.get("Host").map(String::as_str).unwrap_or("-"),
+                                method: request.method.as_str(),
+                                path: &request.path,
+                                version: &request.version,
+                                status: response.status_code,
+                                bytes: response.body.len(),
+                                duration: started.elapsed(),
+                            };
+                            println!("{}", entry.format(format));
+                        }
+                        // Bytes after this request (a pipelined request) stay buffered
+                        connection.parser.reset();
+                        connection.body_started = None;
+                        if close {
+                            connection.is_closing = true;
+                            break 'read;
+                        }
+                        connection.parser.parse(&[]);
+                    }
+                    if connection.body_started.is_none() && matches!(
+                        connection.parser.state,
+                        ParseState::Body | ParseState::ChunkSize | ParseState::ChunkData | ParseState::ChunkTrailer
+                    ) {
+                        connection.body_started = Some(Instant::now());
+                    }
+                    if connection.body_started.is_some() {
+                        // Refuse an oversized body as soon as its size is known instead of buffering it
+                        let parser = &connection.parser;
+                        let size = parser.content_length().unwrap_or(parser.request.body.len());
+                        if self.router.body_limit(&parser.request, connection.listener_server).is_some_and(|max| size > max) {
+                            connection.parser.discard();
+                            let mut response = Response::new(413);
+                            response.headers.insert("Connection".to_string(), "close".to_string());
+                            connection.response_buf.extend_from_slice(&response.to_bytes());
+                            connection.is_closing = true;
+                            break;
+                        }
+                    }
+                    if connection.parser.state == ParseState::Error {
+                        // The stream position is unknown after a malformed request, so always close
+                        let mut response = Response::new(connection.parser.error_status);
+                        response.headers.insert("Connection".to_string(), "close".to_string());
+                        connection.response_buf.extend_from_slice(&response.to_bytes());
+                        connection.is_closing = true;
+                        break;
+                    }
+                    let total = self.total_buffered.saturating_sub(before) + connection.buffered();
+                    if self.max_total_buffer.is_some_and(|limit| total > limit) && connection.buffered() >= fair_share {
+                        if !connection.response_buf.is_empty() {
+                            connection.read_paused = true;
+                        } else {
+                            // Nothing to drain: a request part way in only grows until it
+                            // completes, so refuse it rather than let a slow upload hold memory
+                            connection.parser.discard();
+                            let mut response = Response::new(503);
+                            response.headers.insert("Connection".to_string(), "close".to_string());
+                            if let Some(retry_after) = &self.retry_after {
+                                response.headers.insert("Retry-After".to_string(), retry_after.header_value());
+                            }
+                            connection.response_buf.extend_from_slice(&response.to_bytes());
+                            connection.is_closing = true;
+                        }
+                        break;
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(_) => {
+                    connection.is_closing = true;
+                    break;
+                }
+            }
+        }
+
+        self.total_buffered = self.total_buffered.saturating_sub(before) + connection.buffered();
+        self.connections.insert(token, connection);
+    }
+
+    // `current` is the asking connection, out of the map while its request is handled
+    fn status_response(&self, token: Token, current: &Connection) -> Response {
+        let allowed = match &self.status_allow {
+            Some(allow) => allow.contains(&current.peer.ip().to_canonical()),
+            None => current.peer.ip().is_loopback(),
+        };
+        if !allowed {
+            return Response::new(403);
+        }
+        let mut rows: Vec<(Token, &Connection)> = self.connections.iter().map(|(t, c)| (*t, c)).collect();
+        rows.push((token, current));
+        rows.sort_by_key(|(t, _)| t.0);
+
+        let mut body = String::from("token\tpeer\tage_s\tidle_s\tstate\tpending_bytes\n");
+        for (token, conn) in rows {
+            body.push_str(&format!(
+                "{}\t{}\t{}\t{}\t{:?}\t{}\n",
+                token.0,
+                conn.peer,
+                conn.created.elapsed().as_secs(),
+                conn.last_activity.elapsed().as_secs(),
+                conn.parser.state,
+                conn.buffered()
+            ));
+        }
+        let mut response = Response::new(200);
+        response.headers.insert("Content-Type".to_string(), "text/plain".to_string());
+        response.body = body.into_bytes();
+        response
+    }
+
+    fn write_connection(&mut self, token: Token) {
+        let connection = match self.connections.get_mut(&token) {
+            Some(c) => c,
+            None => return,
+        };
+        connection.last_activity = Instant::now();
+        if connection.response_buf.is_empty() {
+            return;
+        }
+        let before = connection.buffered();
+
+        if !write_pending(&mut connection.socket, &mut connection.response_buf, &mut connection.zero_writes) {
+            connection.is_closing = true;
+        }
+
+        self.total_buffered = self.total_buffered.saturating_sub(before) + connection.buffered();
+    }
+
+    fn check_soft_cap(&mut self) {
+        let Some(soft) = self.soft_max_connections else { return };
+        if self.above_soft_cap || self.connections.len() <= soft {
+            return;
+        }
+        self.above_soft_cap = true;
+        if self.last_soft_cap_warning.is_none_or(|t| t.elapsed() >= SOFT_CAP_WARN_INTERVAL) {
+            self.last_soft_cap_warning = Some(Instant::now());
+            log::warn!("{} active connections, above soft_max_connections ({})", self.connections.len(), soft);
+        }
+    }
+
+    // Best effort: a fresh socket's send buffer takes the short reply, and it is dropped right after
+    fn reject_busy(&self, socket: &mut TcpStream) {
+        let mut response = Response::new(503);
+        response.headers.insert("Connection".to_string(), "close".to_string());
+        if let Some(retry_after) = &self.retry_after {
+            response.headers.insert("Retry-After".to_string(), retry_after.header_value());
+        }
+        let _ = socket.write_all(&response.to_bytes());
+    }
+
+    fn accept_connections(&mut self, idx: usize) -> io::Result<()> {
+        loop {
+            if let Some(limit) = self.max_accepts_per_sec {
+                if self.accept_window_start.elapsed() >= ACCEPT_WINDOW {
+                    self.accept_window_start = Instant::now();
+                    self.accepts_in_window = 0;
+                }
+                if self.accepts_in_window >= limit {
+                    // Leave the rest in the backlog until the next window
+                    if !self.deferred_listeners.contains(&idx) {
+                        self.deferred_listeners.push(idx);
+                    }
+                    return Ok(());
+                }
+            }
+
+            match self.listeners[idx].0.accept() {
+                Ok((mut socket, peer)) => {
+                    self.accepts_in_window += 1;
+                    if let Some(max) = self.max_connections_per_ip {
+                        let ip = peer.ip().to_canonical();
+                        let open = self.connections_per_ip.get(&ip).copied().unwrap_or(0);
+                        if open >= max {
+                            log::warn!("rejecting connection from {}: {} already open", peer, open);
+                            self.reject_busy(&mut socket);
+                            continue;
+                        }
+                        self.connections_per_ip.insert(ip, open + 1);
+                    }
+                    let conn_token = Token(self.next_token);
+                    self.next_token += 1;
+
+                    self.poll.registry().register(
+                        &mut socket,
+                        conn_token,
+                        Interest::READABLE | Interest::WRITABLE,
+                    )?;
+
+                    let mut parser = Parser::new();
+                    parser.max_uri_length = self.max_uri_length;
+                    parser.lenient_methods = self.lenient_methods;
+                    self.connections.insert(conn_token, Connection {
+                        socket,
+                        peer,
+                        listener_server: self.listener_servers[idx],
+                        parser,
+                        response_buf: Vec::new(),
+                        is_closing: false,
+                        last_activity: Instant::now(),
+                        bytes_received: 0,
+                        zero_writes: 0,
+                        read_paused: false,
+                        body_started: None,
+                        created: Instant::now(),
+                    });
+                    self.check_soft_cap();
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+/// Writes what the peer will take of `pending`. Returns false, with `pending` cleared, once
+/// the peer should be dropped: after a write error, or once `MAX_ZERO_WRITES` writes in a row
+/// accept nothing, so a stuck peer can't keep the loop spinning.
+pub fn write_pending<W: Write>(writer: &mut W, pending: &mut Vec<u8>, zero_writes: &mut u8) -> bool {
+    match writer.write(pending) {
+        Ok(0) => {
+            *zero_writes += 1;
+            if *zero_writes < MAX_ZERO_WRITES {
+                return true;
+            }
+        }
+        Ok(n) => {
+            *zero_writes = 0;
+            pending.drain(..n);
+            return true;
+        }
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+        Err(_) => {}
+    }
+    pending.clear();
+    false
+}
+
+// A `Connection: close` request ends the connection after its response
+fn wants_close(request: &Request) -> bool {
+    request.headers.iter().any(|(name, value)| {
+        name.eq_ignore_ascii_case("Connection") && value.split(',').any(|token| token.trim().eq_ignore_ascii_case("close"))
+    })
+}
+
+fn release_ip(per_ip: &mut HashMap<IpAddr, usize>, ip: IpAddr) {
+    let ip = ip.to_canonical();
+    if let Some(open) = per_ip.get_mut(&ip) {
+        *open -= 1;
+        if *open == 0 {
+            per_ip.remove(&ip);
         }
     }
 }
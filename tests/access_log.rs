@@ -0,0 +1,58 @@
+use rust_localserver::access_log::{body_preview, sampled, AccessEntry};
+use rust_localserver::config::LogFormat;
+use std::time::Duration;
+
+#[test]
+fn text_body_is_truncated() {
+    assert_eq!(body_preview(b"{\"event\":\"push\"}", 64), r#""{\"event\":\"push\"}""#);
+    assert_eq!(body_preview(b"hello world", 5), "\"hello\"... (11 bytes)");
+    // Cutting inside a multi-byte character keeps it text
+    assert_eq!(body_preview("héllo".as_bytes(), 2), "\"h\"... (6 bytes)");
+}
+
+#[test]
+fn binary_body_is_summarized() {
+    let png = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR\0\0\0\x10";
+    let preview = body_preview(png, 1024);
+    assert_eq!(preview, "<binary, 20 bytes: 89 50 4e 47 0d 0a 1a 0a 00 00 00 0d 49 48 44 52 ...>");
+    assert!(!preview.contains("PNG"));
+}
+
+#[test]
+fn json_entry_is_one_valid_object() {
+    let entry = AccessEntry {
+        request_id: 42,
+        peer: "[::1]:50000".parse().unwrap(),
+        host: "example.test",
+        method: "GET",
+        path: "/a \"quoted\"\\path\u{1}",
+        version: "HTTP/1.1",
+        status: 404,
+        bytes: 1234,
+        duration: Duration::from_micros(1500),
+    };
+    let line = entry.format(&LogFormat::Json);
+    assert!(!line.contains('\n'));
+    let json: serde_json::Value = serde_json::from_str(&line).unwrap();
+    assert_eq!(json["request_id"], 42);
+    assert_eq!(json["peer"], "::1");
+    assert_eq!(json["host"], "example.test");
+    assert_eq!(json["method"], "GET");
+    assert_eq!(json["path"], "/a \"quoted\"\\path\u{1}");
+    assert_eq!(json["status"], 404);
+    assert_eq!(json["bytes"], 1234);
+    assert_eq!(json["duration_ms"].as_f64(), Some(1.5));
+}
+
+#[test]
+fn sampling_logs_about_the_configured_fraction() {
+    for rate in [0.1, 0.25, 0.5] {
+        let logged = (1..=10_000).filter(|&id| sampled(id, rate)).count();
+        let expected = 10_000.0 * rate;
+        assert!((logged as f64 - expected).abs() < expected * 0.1, "rate {}: {} logged", rate, logged);
+    }
+    assert_eq!((1..=1000).filter(|&id| sampled(id, 0.0)).count(), 0);
+    assert!((1..=1000).all(|id| sampled(id, 1.0)));
+    // Raising the rate only adds requests; everything logged before still is
+    assert!((1..=1000).filter(|&id| sampled(id, 0.1)).all(|id| sampled(id, 0.5)));
+}
@@ -0,0 +1,139 @@
+use rust_localserver::config::Config;
+use rust_localserver::http::Request;
+use rust_localserver::router::Router;
+use std::fs;
+
+fn load(name: &str, yaml: &str) -> Result<Config, String> {
+    let path = std::env::temp_dir().join(format!("rust-localserver-{}-{}.yaml", name, std::process::id()));
+    fs::write(&path, yaml).unwrap();
+    let config = Config::from_file(&path).map_err(|e| e.to_string());
+    let _ = fs::remove_file(&path);
+    config
+}
+
+const TEMPLATED: &str = r#"
+route_templates:
+  site:
+    - path: "/"
+      root: "/srv/www"
+    - path: "/api"
+      root: "/srv/api"
+      methods: ["POST"]
+servers:
+  - host: "127.0.0.1"
+    ports: [8080]
+    use: site
+  - host: "127.0.0.1"
+    ports: [8081]
+    use: site
+    routes:
+      - path: "/api"
+        root: "/srv/api-v2"
+"#;
+
+#[test]
+fn servers_sharing_a_template_route_alike() {
+    let config = load("templated", TEMPLATED).unwrap();
+    let router = Router::new(config);
+    for server in 0..2 {
+        assert_eq!(router.find_route(server, "/index.html").unwrap().root.as_deref(), Some("/srv/www"));
+        assert_eq!(router.find_route(server, "/api/users").unwrap().path, "/api");
+    }
+    // The server's own route shadows the template's
+    assert_eq!(router.find_route(0, "/api/users").unwrap().root.as_deref(), Some("/srv/api"));
+    assert_eq!(router.find_route(1, "/api/users").unwrap().root.as_deref(), Some("/srv/api-v2"));
+}
+
+#[test]
+fn unknown_template_is_rejected() {
+    let err = load("unknown-template", "servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    use: missing\n").unwrap_err();
+    assert!(err.contains("missing"), "{}", err);
+}
+
+#[test]
+fn config_without_servers_is_rejected() {
+    let err = load("no-servers", "servers: []\n").unwrap_err();
+    assert!(err.contains("at least one server"), "{}", err);
+    let err = load("servers-missing", "max_routes: 4\n").unwrap_err();
+    assert!(err.contains("servers"), "{}", err);
+}
+
+#[test]
+fn server_without_routes_is_rejected() {
+    for routes in ["", "    routes: []\n"] {
+        let yaml = format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}", routes);
+        let err = load("no-routes", &yaml).unwrap_err();
+        assert!(err.contains("server 127.0.0.1 has no routes"), "{}", err);
+    }
+}
+
+#[test]
+fn server_without_ports_listens_on_80() {
+    let config = load("no-ports", "servers:\n  - host: \"127.0.0.1\"\n    routes:\n      - path: \"/\"\n        root: \"/srv/www\"\n").unwrap();
+    assert_eq!(config.servers[0].listen_ports(), &[80]);
+    let config = load("ports", "servers:\n  - host: \"127.0.0.1\"\n    ports: [8080, 8081]\n    routes:\n      - path: \"/\"\n        root: \"/srv/www\"\n").unwrap();
+    assert_eq!(config.servers[0].listen_ports(), &[8080, 8081]);
+}
+
+#[test]
+fn more_routes_than_max_routes_is_rejected() {
+    let routes = "    routes:\n      - path: \"/\"\n        root: \"/srv\"\n      - path: \"/a\"\n        root: \"/srv\"\n";
+    let yaml = format!("max_routes: 1\nservers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}", routes);
+    let err = load("max-routes", &yaml).unwrap_err();
+    assert!(err.contains("max_routes"), "{}", err);
+    assert!(load("max-routes-ok", &yaml.replace("max_routes: 1", "max_routes: 2")).is_ok());
+}
+
+#[test]
+fn default_error_pages_fill_in_per_status() {
+    let dir = std::env::temp_dir().join(format!("rust-localserver-error-pages-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for name in ["global-404.html", "global-500.html", "own-500.html"] {
+        fs::write(dir.join(name), name).unwrap();
+    }
+    let yaml = format!(
+        "default_error_pages:\n  404: \"{dir}/global-404.html\"\n  500: \"{dir}/global-500.html\"\nservers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{dir}\"\n  - host: \"127.0.0.1\"\n    ports: [8081]\n    error_pages:\n      500: \"{dir}/own-500.html\"\n    routes:\n      - path: \"/\"\n        root: \"{dir}\"\n",
+        dir = dir.display()
+    );
+    let config = load("default-error-pages", &yaml).unwrap();
+    let pages = |server: usize| config.servers[server].error_pages.clone().unwrap();
+    assert_eq!(pages(0)[&404], format!("{}/global-404.html", dir.display()));
+    // A server's own page wins; the statuses it leaves out still come from the defaults
+    assert_eq!(pages(1)[&500], format!("{}/own-500.html", dir.display()));
+    assert_eq!(pages(1)[&404], format!("{}/global-404.html", dir.display()));
+
+    let router = Router::new(config);
+    let mut request = Request::new();
+    request.path = "/missing.html".to_string();
+    assert_eq!(router.handle(&request, 0).body, b"global-404.html");
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn close_on_error_policies() {
+    use rust_localserver::config::CloseOnError;
+    let policy = |value: &str| serde_yaml::from_str::<CloseOnError>(value).unwrap();
+    let closes = |value: &str| [200, 404, 500].map(|status| policy(value).closes(status));
+    assert_eq!(closes("4xx"), [false, true, false]);
+    assert_eq!(closes("5xx"), [false, false, true]);
+    assert_eq!(closes("always"), [false, true, true]);
+    assert_eq!(closes("never"), [false, false, false]);
+}
+
+#[test]
+fn relative_roots_follow_the_configured_mode() {
+    let yaml = |mode: &str| format!(
+        "{}servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"site/www\"\n      - path: \"/abs\"\n        root: \"/srv/abs\"\n",
+        mode
+    );
+    let roots = |config: Config| config.servers[0].routes.iter().map(|r| r.root.clone().unwrap()).collect::<Vec<_>>();
+
+    assert_eq!(roots(load("roots-cwd", &yaml("")).unwrap()), ["site/www", "/srv/abs"]);
+    // Against the directory holding the config file, wherever the server was started
+    let config_dir = std::env::temp_dir().join("site/www").to_string_lossy().into_owned();
+    assert_eq!(roots(load("roots-config-dir", &yaml("relative_roots: config_dir\n")).unwrap()), [config_dir.as_str(), "/srv/abs"]);
+
+    let err = load("roots-reject", &yaml("relative_roots: reject\n")).unwrap_err();
+    assert!(err.contains("route / has a relative root"), "{}", err);
+    assert!(load("roots-reject-abs", &yaml("relative_roots: reject\n").replace("site/www", "/srv/www")).is_ok());
+}
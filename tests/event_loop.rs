@@ -0,0 +1,680 @@
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+static NEXT_SITE: AtomicUsize = AtomicUsize::new(0);
+
+/// A server process running against a scratch site, killed on drop.
+struct TestServer {
+    child: Child,
+    port: u16,
+    dir: PathBuf,
+}
+
+impl TestServer {
+    fn start() -> Self {
+        Self::start_with("")
+    }
+
+    /// `globals` is YAML placed before `servers:`, for top-level settings.
+    fn start_with(globals: &str) -> Self {
+        Self::start_with_routes(globals, "")
+    }
+
+    /// `routes` is YAML for extra route entries, matched before the default ones;
+    /// `{dir}` in it stands for the site's scratch directory.
+    fn start_with_routes(globals: &str, routes: &str) -> Self {
+        Self::launch("127.0.0.1", globals, "", routes)
+    }
+
+    /// `server` is YAML for extra keys on the server entry itself.
+    fn launch(host: &str, globals: &str, server: &str, routes: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "rust-localserver-test-{}-{}",
+            std::process::id(),
+            NEXT_SITE.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(dir.join("www")).unwrap();
+        fs::create_dir_all(dir.join("uploads")).unwrap();
+        fs::write(dir.join("www/hello.txt"), "hello world").unwrap();
+
+        // Grab a free port; the server binds it again right after
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let config = format!(
+            "{globals}servers:\n  - host: \"{host}\"\n    ports: [{port}]\n{server}    routes:\n{routes}      - path: \"/uploads\"\n        root: \"{uploads}\"\n        allow_uploads: true\n      - path: \"/\"\n        root: \"{www}\"\n",
+            globals = globals,
+            host = host,
+            port = port,
+            server = server,
+            routes = routes.replace("{dir}", &dir.display().to_string()),
+            uploads = dir.join("uploads").display(),
+            www = dir.join("www").display(),
+        );
+        let config_path = dir.join("config.yaml");
+        fs::write(&config_path, config).unwrap();
+
+        // Warnings go to a file in the site, for tests that check what was logged
+        let log = fs::File::create(dir.join("server.log")).unwrap();
+        let child = Command::new(env!("CARGO_BIN_EXE_rust-localserver"))
+            .arg(&config_path)
+            .env("RUST_LOG", "warn")
+            .stdout(std::process::Stdio::null())
+            .stderr(log)
+            .spawn()
+            .unwrap();
+        let server = TestServer { child, port, dir };
+        for _ in 0..100 {
+            if TcpStream::connect(("127.0.0.1", server.port)).is_ok() {
+                return server;
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("server did not start listening on port {}", server.port);
+    }
+
+    fn connect(&self) -> TcpStream {
+        let stream = TcpStream::connect(("127.0.0.1", self.port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream
+    }
+
+    fn log(&self) -> String {
+        fs::read_to_string(self.dir.join("server.log")).unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Reply {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, v)| v.as_str())
+    }
+}
+
+/// Reads one response. Every response with a body must be framed by Content-Length,
+/// so a missing one fails the test rather than reading until close.
+fn read_reply(stream: &mut TcpStream) -> Reply {
+    let mut reply = read_head(stream);
+    let bodiless = (100..200).contains(&reply.status) || reply.status == 204 || reply.status == 304;
+    let length = match reply.header("Content-Length") {
+        Some(value) => value.parse::<usize>().unwrap(),
+        None if bodiless => 0,
+        None => panic!("{} response without Content-Length", reply.status),
+    };
+    reply.body.resize(length, 0);
+    stream.read_exact(&mut reply.body).unwrap();
+    reply
+}
+
+/// Reads a response's status line and headers only, as for HEAD.
+fn read_head(stream: &mut TcpStream) -> Reply {
+    let mut buf = Vec::new();
+    let mut byte = [0; 1];
+    while !buf.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut byte) {
+            Ok(0) => panic!("connection closed before headers: {:?}", String::from_utf8_lossy(&buf)),
+            Ok(_) => buf.push(byte[0]),
+            Err(e) => panic!("reading headers: {}", e),
+        }
+    }
+    let head = String::from_utf8(buf).unwrap();
+    let mut lines = head.split("\r\n");
+    let status = lines.next().unwrap().split(' ').nth(1).unwrap().parse().unwrap();
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|l| l.split_once(':'))
+        .map(|(n, v)| (n.trim().to_string(), v.trim().to_string()))
+        .collect();
+    Reply { status, headers, body: Vec::new() }
+}
+
+/// Connects to the server from `127.0.0.2`, which Linux routes over loopback like
+/// `127.0.0.1`, so the server sees a second client address.
+#[cfg(target_os = "linux")]
+fn connect_as_other_client(port: u16) -> TcpStream {
+    use socket2::{Domain, Socket, Type};
+    let socket = Socket::new(Domain::IPV4, Type::STREAM, None).unwrap();
+    socket.bind(&"127.0.0.2:0".parse::<std::net::SocketAddr>().unwrap().into()).unwrap();
+    socket.connect(&std::net::SocketAddr::from(([127, 0, 0, 1], port)).into()).unwrap();
+    let stream: TcpStream = socket.into();
+    stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    stream
+}
+
+fn request(server: &TestServer, raw: &str) -> Reply {
+    let mut stream = server.connect();
+    stream.write_all(raw.as_bytes()).unwrap();
+    read_reply(&mut stream)
+}
+
+#[test]
+fn static_get_returns_file() {
+    let server = TestServer::start();
+    let reply = request(&server, "GET /hello.txt HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.body, b"hello world");
+    assert_eq!(reply.header("Content-Type"), Some("text/plain"));
+}
+
+#[test]
+fn missing_file_returns_404() {
+    let server = TestServer::start();
+    let reply = request(&server, "GET /nope.txt HTTP/1.1\r\nHost: localhost\r\n\r\n");
+    assert_eq!(reply.status, 404);
+    assert!(String::from_utf8_lossy(&reply.body).contains("404"));
+}
+
+#[test]
+fn chunked_post_is_reassembled() {
+    let server = TestServer::start();
+    let reply = request(
+        &server,
+        "POST /uploads HTTP/1.1\r\nHost: localhost\r\nX-Filename: note.txt\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n",
+    );
+    assert_eq!(reply.status, 201);
+    assert_eq!(fs::read(server.dir.join("uploads/note.txt")).unwrap(), b"hello world");
+}
+
+#[test]
+fn keep_alive_serves_two_requests() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+    stream.write_all(b"GET /hello.txt HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let first = read_reply(&mut stream);
+    stream.write_all(b"GET /nope HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+    let second = read_reply(&mut stream);
+    assert_eq!(first.status, 200);
+    assert_eq!(second.status, 404);
+}
+
+#[test]
+fn pipelined_requests_are_all_answered() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+    stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\nGET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream).status, 200);
+    assert_eq!(read_reply(&mut stream).status, 200);
+}
+
+#[test]
+fn malformed_request_gets_400_and_close() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+    stream.write_all(b"GARBAGE\r\n\r\n").unwrap();
+    let reply = read_reply(&mut stream);
+    assert_eq!(reply.status, 400);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    let mut rest = Vec::new();
+    assert_eq!(stream.read_to_end(&mut rest).unwrap(), 0);
+}
+
+#[test]
+fn busy_connection_is_closed_once_past_lifetime() {
+    let server = TestServer::start_with("max_connection_lifetime: 1\n");
+    let mut stream = server.connect();
+    let mut replies = 0;
+    loop {
+        // Expiry while idle between requests closes without a response
+        let sent = stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").is_ok();
+        if !sent || stream.peek(&mut [0]).unwrap_or(0) == 0 {
+            break;
+        }
+        let reply = read_reply(&mut stream);
+        assert_eq!(reply.status, 200);
+        replies += 1;
+        if reply.header("Connection") == Some("close") {
+            let mut rest = Vec::new();
+            assert_eq!(stream.read_to_end(&mut rest).unwrap(), 0);
+            break;
+        }
+        assert!(replies < 30, "connection outlived its lifetime");
+        thread::sleep(Duration::from_millis(100));
+    }
+    assert!(replies > 1);
+}
+
+#[test]
+fn retry_after_is_sent_as_seconds() {
+    let server = TestServer::start_with_routes("retry_after: 120\n", "      - path: \"/busy\"\n        return_status: 503\n");
+    let reply = request(&server, "GET /busy HTTP/1.1\r\n\r\n");
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.header("Retry-After"), Some("120"));
+    // Other statuses don't carry it
+    let reply = request(&server, "GET /hello.txt HTTP/1.1\r\n\r\n");
+    assert_eq!(reply.header("Retry-After"), None);
+}
+
+#[test]
+fn retry_after_is_sent_as_http_date() {
+    let server = TestServer::start_with_routes(
+        "retry_after: \"Fri, 31 Dec 2100 23:59:59 GMT\"\n",
+        "      - path: \"/slow-down\"\n        return_status: 429\n",
+    );
+    let reply = request(&server, "GET /slow-down HTTP/1.1\r\n\r\n");
+    assert_eq!(reply.status, 429);
+    assert_eq!(reply.header("Retry-After"), Some("Fri, 31 Dec 2100 23:59:59 GMT"));
+}
+
+#[test]
+fn connections_past_per_ip_limit_get_503() {
+    let server = TestServer::start_with("max_connections_per_ip: 2\nretry_after: 5\n");
+    // Let the server reap the probe connection made while starting
+    thread::sleep(Duration::from_millis(200));
+    let mut first = server.connect();
+    let mut second = server.connect();
+    for stream in [&mut first, &mut second] {
+        stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_reply(stream).status, 200);
+    }
+    let mut third = server.connect();
+    let reply = read_reply(&mut third);
+    assert_eq!(reply.status, 503);
+    assert_eq!(reply.header("Retry-After"), Some("5"));
+
+    // The limit is per address; another client is still let in
+    #[cfg(target_os = "linux")]
+    {
+        let mut other = connect_as_other_client(server.port);
+        other.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+        assert_eq!(read_reply(&mut other).status, 200);
+    }
+
+    // Closing one frees a slot once the server notices
+    drop(first);
+    thread::sleep(Duration::from_millis(200));
+    let mut again = server.connect();
+    again.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(read_reply(&mut again).status, 200);
+    drop(second);
+}
+
+#[test]
+fn control_byte_in_header_value_gets_400() {
+    let server = TestServer::start();
+    for raw in ["GET /hello.txt HTTP/1.1\r\nX-Note: a\x0bb\r\n\r\n", "GET /hello.txt HTTP/1.1\r\nX-Note: a\rb\r\n\r\n"] {
+        let reply = request(&server, raw);
+        assert_eq!(reply.status, 400);
+        assert_eq!(reply.header("Connection"), Some("close"));
+    }
+    // Tabs are allowed inside values
+    assert_eq!(request(&server, "GET /hello.txt HTTP/1.1\r\nX-Note: a\tb\r\n\r\n").status, 200);
+}
+
+#[test]
+fn head_sends_headers_only_and_get_still_gets_body() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+    stream.write_all(b"HEAD /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    let head = read_head(&mut stream);
+    assert_eq!(head.status, 200);
+    assert_eq!(head.header("Content-Length"), Some("11"));
+    // Nothing but the next response may follow the HEAD headers
+    stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    let get = read_reply(&mut stream);
+    assert_eq!(get.status, 200);
+    assert_eq!(get.body, b"hello world");
+}
+
+#[test]
+fn unknown_method_gets_501_and_refused_known_method_405() {
+    let server = TestServer::start_with_routes("", "      - path: \"/readonly\"\n        root: \"/tmp\"\n        methods: [\"GET\", \"PATCH\"]\n");
+    assert_eq!(request(&server, "FOOBAR /hello.txt HTTP/1.1\r\n\r\n").status, 501);
+    // Listed on the route, but only CGI could implement it
+    assert_eq!(request(&server, "PATCH /readonly/x HTTP/1.1\r\n\r\n").status, 501);
+    let reply = request(&server, "DELETE /readonly/x HTTP/1.1\r\n\r\n");
+    assert_eq!(reply.status, 405);
+    assert_eq!(reply.header("Allow"), Some("GET, PATCH"));
+}
+
+#[test]
+fn status_endpoint_lists_open_connections() {
+    let server = TestServer::start_with("status_path: /__status\n");
+    let mut idle = server.connect();
+    idle.write_all(b"GET /hel").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    let reply = request(&server, "GET /__status HTTP/1.1\r\n\r\n");
+    assert_eq!(reply.status, 200);
+    let table = String::from_utf8(reply.body).unwrap();
+    let row = table.lines()
+        .find(|l| l.contains(&idle.local_addr().unwrap().to_string()))
+        .unwrap_or_else(|| panic!("idle connection missing from:\n{}", table));
+    assert!(row.contains("RequestLine"), "{}", row);
+    assert!(row.ends_with("\t8"), "{}", row);
+}
+
+#[test]
+fn status_endpoint_refuses_clients_not_allowed() {
+    let server = TestServer::start_with("status_path: /__status\nstatus_allow: [\"10.9.9.9\"]\n");
+    assert_eq!(request(&server, "GET /__status HTTP/1.1\r\n\r\n").status, 403);
+}
+
+#[test]
+fn explicit_keep_alive_only_when_configured() {
+    let server = TestServer::start_with("explicit_keep_alive: true\n");
+    let mut stream = server.connect();
+    for _ in 0..2 {
+        stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+        let reply = read_reply(&mut stream);
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.header("Connection"), Some("keep-alive"));
+    }
+    // A closing response says close instead
+    stream.write_all(b"GARBAGE\r\n\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream).header("Connection"), Some("close"));
+
+    let server = TestServer::start();
+    assert_eq!(request(&server, "GET /hello.txt HTTP/1.1\r\n\r\n").header("Connection"), None);
+}
+
+#[test]
+fn request_asking_for_close_is_not_kept_alive() {
+    let server = TestServer::start_with("explicit_keep_alive: true\n");
+    let mut stream = server.connect();
+    stream.write_all(b"GET /hello.txt HTTP/1.1\r\nconnection: Close\r\n\r\n").unwrap();
+    let reply = read_reply(&mut stream);
+    assert_eq!(reply.status, 200);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn failed_bind_on_second_port_releases_the_first() {
+    let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+    let busy = taken.local_addr().unwrap().port();
+    let free = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let dir = std::env::temp_dir().join(format!("rust-localserver-bind-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config_path = dir.join("config.yaml");
+    fs::write(
+        &config_path,
+        format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [{}, {}]\n    routes:\n      - path: \"/\"\n        root: \"/tmp\"\n", free, busy),
+    ).unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_rust-localserver")).arg(&config_path).output().unwrap();
+    let _ = fs::remove_dir_all(&dir);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains(&format!("binding 127.0.0.1:{}", busy)));
+    // The first port was never announced, and nothing answers on it now
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Listening on"));
+    assert!(TcpStream::connect(("127.0.0.1", free)).is_err());
+}
+
+#[test]
+fn plain_listener_redirects_to_https() {
+    let server = TestServer::launch("127.0.0.1", "", "    listen_mode: redirect_https\n    https_port: 8443\n", "");
+    let reply = request(&server, "GET /hello.txt?lang=en HTTP/1.1\r\nHost: example.test:8080\r\n\r\n");
+    assert_eq!(reply.status, 301);
+    assert_eq!(reply.header("Location"), Some("https://example.test:8443/hello.txt?lang=en"));
+    assert_eq!(reply.header("Content-Length"), Some("0"));
+}
+
+#[test]
+fn slow_uploads_are_shed_to_keep_buffering_under_the_ceiling() {
+    const CEILING: usize = 32 * 1024;
+    const CLIENTS: usize = 8;
+    let server = TestServer::start_with(&format!("max_total_buffer: {}\nstatus_path: /__status\n", CEILING));
+    // Each announces a large body and trickles in part of it
+    let mut clients: Vec<TcpStream> = (0..CLIENTS).map(|_| server.connect()).collect();
+    for client in &mut clients {
+        client.write_all(b"POST /uploads/slow.bin HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n").unwrap();
+    }
+    for _ in 0..8 {
+        for client in &mut clients {
+            let _ = client.write_all(&[b'x'; 1024]);
+        }
+        thread::sleep(Duration::from_millis(20));
+    }
+    thread::sleep(Duration::from_millis(100));
+
+    let table = String::from_utf8(request(&server, "GET /__status HTTP/1.1\r\n\r\n").body).unwrap();
+    let pending: usize = table.lines().skip(1).map(|row| row.rsplit('\t').next().unwrap().parse::<usize>().unwrap()).sum();
+    // Each connection may overshoot by at most one read before it is shed
+    assert!(pending <= CEILING + CLIENTS * 4096, "{} bytes buffered:\n{}", pending, table);
+
+    let shed = clients.iter().filter(|client| {
+        let mut stream: &TcpStream = client;
+        stream.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+        let mut status = [0; 12];
+        stream.read_exact(&mut status).is_ok() && &status == b"HTTP/1.1 503"
+    }).count();
+    assert!(shed > 0 && shed < CLIENTS, "{} of {} shed", shed, CLIENTS);
+}
+
+#[test]
+fn body_over_client_max_body_size_gets_413_before_it_arrives() {
+    let server = TestServer::launch("127.0.0.1", "", "    client_max_body_size: 16\n", "");
+    // Headers only: the declared length alone is enough to refuse
+    let reply = request(&server, "POST /uploads/big.bin HTTP/1.1\r\nContent-Length: 1000000\r\n\r\n");
+    assert_eq!(reply.status, 413);
+    assert_eq!(reply.header("Connection"), Some("close"));
+
+    let mut stream = server.connect();
+    stream.write_all(b"POST /uploads/big.bin HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n10\r\n0123456789abcdef\r\n").unwrap();
+    stream.write_all(b"1\r\nx\r\n0\r\n\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream).status, 413);
+
+    let reply = request(&server, "POST /uploads/small.bin HTTP/1.1\r\nContent-Length: 16\r\n\r\n0123456789abcdef");
+    assert_eq!(reply.status, 201);
+}
+
+#[test]
+fn burst_of_connections_is_accepted_at_the_configured_pace() {
+    let server = TestServer::start_with("max_accepts_per_sec: 4\n");
+    // Let the window the startup probe used run out
+    thread::sleep(Duration::from_millis(1100));
+    let start = std::time::Instant::now();
+    let mut clients: Vec<TcpStream> = (0..10).map(|_| server.connect()).collect();
+    for client in &mut clients {
+        client.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    }
+    let mut answered = Vec::new();
+    for client in &mut clients {
+        assert_eq!(read_reply(client).status, 200);
+        answered.push(start.elapsed());
+    }
+    // Four per one-second window: nothing is refused, the rest just waits. The first
+    // window opens no earlier than `start`, so these are lower bounds a slow runner can't break.
+    let first_window = answered.iter().filter(|t| **t < Duration::from_secs(1)).count();
+    assert!(first_window <= 4, "{:?}", answered);
+    assert!(answered[4] >= Duration::from_secs(1), "{:?}", answered);
+    assert!(answered[8] >= Duration::from_secs(2), "{:?}", answered);
+}
+
+#[test]
+fn connection_closed_without_a_request_is_dropped_quietly() {
+    let server = TestServer::start_with("status_path: /__status\n");
+    let mut silent = server.connect();
+    let silent_addr = silent.local_addr().unwrap().to_string();
+    silent.shutdown(std::net::Shutdown::Write).unwrap();
+    // No response, just the close
+    let mut received = Vec::new();
+    silent.read_to_end(&mut received).unwrap();
+    assert!(received.is_empty(), "{:?}", String::from_utf8_lossy(&received));
+
+    let table = String::from_utf8(request(&server, "GET /__status HTTP/1.1\r\n\r\n").body).unwrap();
+    assert!(!table.contains(&silent_addr), "{}", table);
+}
+
+#[test]
+fn http2_preface_gets_505_and_close() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+    stream.write_all(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n").unwrap();
+    let reply = read_reply(&mut stream);
+    assert_eq!(reply.status, 505);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    let mut rest = Vec::new();
+    stream.read_to_end(&mut rest).unwrap();
+    assert!(rest.is_empty());
+}
+
+#[test]
+fn close_on_error_decides_whether_a_404_ends_the_connection() {
+    for (globals, closes) in [("", false), ("close_on_error: 4xx\n", true), ("close_on_error: never\n", false)] {
+        let server = TestServer::start_with(globals);
+        let mut stream = server.connect();
+        stream.write_all(b"GET /missing.txt HTTP/1.1\r\n\r\n").unwrap();
+        let reply = read_reply(&mut stream);
+        assert_eq!(reply.status, 404);
+        assert_eq!(reply.header("Connection") == Some("close"), closes, "{:?}", globals);
+        if !closes {
+            stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+            assert_eq!(read_reply(&mut stream).status, 200);
+        }
+    }
+    // Malformed requests close whatever the policy
+    let server = TestServer::start_with("close_on_error: never\n");
+    assert_eq!(request(&server, "BROKEN\r\n\r\n").header("Connection"), Some("close"));
+}
+
+#[test]
+fn small_request_is_answered_without_waiting_for_the_next_sweep() {
+    let server = TestServer::start();
+    let mut stream = server.connect();
+    stream.set_nodelay(true).unwrap();
+    // Warm up, so accepting and registering the connection isn't timed
+    stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    assert_eq!(read_reply(&mut stream).status, 200);
+    let mut round_trips: Vec<Duration> = (0..11)
+        .map(|_| {
+            let started = std::time::Instant::now();
+            stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+            assert_eq!(read_reply(&mut stream).body, b"hello world");
+            started.elapsed()
+        })
+        .collect();
+    round_trips.sort();
+    // A stalled response waits for the one-second sweep every time; the median is well
+    // clear of that even when a loaded runner delays a few round trips
+    assert!(round_trips[5] < Duration::from_millis(500), "{:?}", round_trips);
+}
+
+#[test]
+fn return_444_route_closes_without_a_response() {
+    let server = TestServer::start_with_routes("", "      - path: \"/blackhole\"\n        return_status: 444\n");
+    let mut stream = server.connect();
+    stream.write_all(b"GET /blackhole/anything HTTP/1.1\r\n\r\n").unwrap();
+    let mut received = Vec::new();
+    stream.read_to_end(&mut received).unwrap();
+    assert!(received.is_empty(), "{:?}", String::from_utf8_lossy(&received));
+    // Other routes are still answered
+    assert_eq!(request(&server, "GET /hello.txt HTTP/1.1\r\n\r\n").status, 200);
+}
+
+#[test]
+fn body_dribbled_past_client_body_timeout_gets_408() {
+    let server = TestServer::start_with("client_body_timeout: 1\n");
+    let mut stream = server.connect();
+    let started = std::time::Instant::now();
+    stream.write_all(b"POST /uploads HTTP/1.1\r\nContent-Length: 10\r\nX-Filename: slow.txt\r\n\r\na").unwrap();
+    // Steady progress doesn't extend the deadline for the whole body
+    for _ in 0..2 {
+        thread::sleep(Duration::from_millis(300));
+        stream.write_all(b"b").unwrap();
+    }
+    let reply = read_reply(&mut stream);
+    assert_eq!(reply.status, 408);
+    assert_eq!(reply.header("Connection"), Some("close"));
+    // Well before the idle timeout could have closed it instead
+    assert!(started.elapsed() < Duration::from_secs(4), "{:?}", started.elapsed());
+    assert!(!server.dir.join("uploads/slow.txt").exists());
+}
+
+#[test]
+fn crossing_soft_max_connections_warns_once() {
+    let server = TestServer::start_with("soft_max_connections: 2\n");
+    for _ in 0..2 {
+        let mut open: Vec<TcpStream> = (0..5).map(|_| server.connect()).collect();
+        // Answered requests mean every connection before them was accepted
+        for stream in &mut open {
+            stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+            assert_eq!(read_reply(stream).status, 200);
+        }
+        drop(open);
+        thread::sleep(Duration::from_millis(100));
+    }
+    let log = server.log();
+    assert_eq!(log.matches("above soft_max_connections (2)").count(), 1, "{}", log);
+}
+
+#[test]
+fn lowercase_method_is_served_only_when_lenient() {
+    let strict = TestServer::start();
+    assert_eq!(request(&strict, "get /hello.txt HTTP/1.1\r\n\r\n").status, 501);
+    let lenient = TestServer::start_with("lenient_methods: true\n");
+    let reply = request(&lenient, "get /hello.txt HTTP/1.1\r\n\r\n");
+    assert_eq!((reply.status, reply.body.as_slice()), (200, &b"hello world"[..]));
+    // Only known methods are folded; the rest stay as sent
+    assert_eq!(request(&lenient, "frobnicate /hello.txt HTTP/1.1\r\n\r\n").status, 501);
+}
+
+#[test]
+fn delete_with_a_body_keeps_the_connection_in_step() {
+    let server = TestServer::start_with_routes("", "      - path: \"/files\"\n        root: \"{dir}/www\"\n        methods: [\"GET\", \"DELETE\"]\n");
+    fs::write(server.dir.join("www/old.txt"), "old").unwrap();
+    let mut stream = server.connect();
+    // The body says nothing about what to delete; it must still be read past
+    stream.write_all(b"DELETE /files/old.txt HTTP/1.1\r\nContent-Length: 17\r\n\r\nGET /hello.txt HT").unwrap();
+    assert_eq!(read_reply(&mut stream).status, 204);
+    assert!(!server.dir.join("www/old.txt").exists());
+    stream.write_all(b"GET /files/hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    let reply = read_reply(&mut stream);
+    assert_eq!((reply.status, reply.body.as_slice()), (200, &b"hello world"[..]));
+}
+
+#[test]
+fn dozens_of_listeners_all_accept_with_a_small_event_capacity() {
+    // Hold the listeners together so the ports are distinct, then free them for the server
+    let reserved: Vec<TcpListener> = (0..40).map(|_| TcpListener::bind("127.0.0.1:0").unwrap()).collect();
+    let ports: Vec<u16> = reserved.iter().map(|l| l.local_addr().unwrap().port()).collect();
+    drop(reserved);
+    let dir = std::env::temp_dir().join(format!("rust-localserver-listeners-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("hello.txt"), "hello world").unwrap();
+    let port_list = ports.iter().map(u16::to_string).collect::<Vec<_>>().join(", ");
+    let config_path = dir.join("config.yaml");
+    fs::write(
+        &config_path,
+        format!("event_capacity: 4\nservers:\n  - host: \"127.0.0.1\"\n    ports: [{}]\n    routes:\n      - path: \"/\"\n        root: \"{}\"\n", port_list, dir.display()),
+    ).unwrap();
+    let child = Command::new(env!("CARGO_BIN_EXE_rust-localserver"))
+        .arg(&config_path)
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .unwrap();
+    let server = TestServer { child, port: ports[0], dir };
+    let started = (0..100).any(|_| {
+        thread::sleep(Duration::from_millis(20));
+        TcpStream::connect(("127.0.0.1", server.port)).is_ok()
+    });
+    assert!(started, "server did not start listening");
+
+    // Connect to every port at once, so one poll sees more ready listeners than it has room for
+    let mut streams: Vec<TcpStream> = ports.iter().map(|&port| TcpStream::connect(("127.0.0.1", port)).unwrap()).collect();
+    for stream in &mut streams {
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        stream.write_all(b"GET /hello.txt HTTP/1.1\r\n\r\n").unwrap();
+    }
+    for stream in &mut streams {
+        assert_eq!(read_reply(stream).body, b"hello world");
+    }
+}
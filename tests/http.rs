@@ -0,0 +1,202 @@
+use rust_localserver::http::{media_quality, normalize_path, parse_accept, Method, ParseState, Parser, Response, REQUEST_LINE_SLACK};
+
+fn parse(input: &[u8], max_uri_length: Option<usize>) -> Parser {
+    let mut parser = Parser::new();
+    parser.max_uri_length = max_uri_length;
+    parser.parse(input);
+    parser
+}
+
+fn parse_line(target: &str, max_uri_length: Option<usize>) -> Parser {
+    parse(format!("GET {} HTTP/1.1\r\nHost: example.test\r\n\r\n", target).as_bytes(), max_uri_length)
+}
+
+#[test]
+fn target_at_max_uri_length_is_accepted() {
+    let target = format!("/{}", "a".repeat(31));
+    assert_eq!(parse_line(&target, Some(32)).state, ParseState::Done);
+    let parser = parse_line(&format!("{}b", target), Some(32));
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 414));
+}
+
+#[test]
+fn max_uri_length_counts_the_encoded_target() {
+    // Decodes to 12 bytes but arrives as 32
+    let target = format!("/{}", "%41".repeat(10) + "b");
+    assert_eq!(target.len(), 32);
+    assert_eq!(parse_line(&target, Some(32)).state, ParseState::Done);
+    let parser = parse_line(&format!("{}c", target), Some(32));
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 414));
+}
+
+#[test]
+fn long_request_line_split_before_its_lf_is_not_cut_short() {
+    // Exactly at the line bound; a read ending on the CR must not tip it over
+    let method = "A".repeat(28 + REQUEST_LINE_SLACK - " /abc HTTP/1.1".len());
+    let input = format!("{} /abc HTTP/1.1\r\n\r\n", method).into_bytes();
+    let line = input.iter().position(|&b| b == b'\r').unwrap();
+    assert_eq!(line, 28 + REQUEST_LINE_SLACK);
+    let mut parser = Parser::new();
+    parser.max_uri_length = Some(28);
+    parser.parse(&input[..=line]);
+    assert_eq!(parser.state, ParseState::RequestLine);
+    parser.parse(&input[line + 1..]);
+    assert_eq!(parser.state, ParseState::Done);
+}
+
+#[test]
+fn no_max_uri_length_means_no_limit() {
+    assert_eq!(parse_line(&format!("/{}", "a".repeat(8192)), None).state, ParseState::Done);
+}
+
+#[test]
+fn leading_blank_lines_are_skipped() {
+    let parser = parse(b"\r\n\r\n\r\nGET /index.html HTTP/1.1\r\nHost: example.test\r\n\r\n", None);
+    assert_eq!(parser.state, ParseState::Done);
+    assert_eq!(parser.request.path, "/index.html");
+
+    // Blank lines split across reads, as between keep-alive requests
+    let mut parser = Parser::new();
+    for read in [&b"\r"[..], b"\n\r", b"\nGET / HTTP/1.1\r\n", b"\r\n"] {
+        parser.parse(read);
+    }
+    assert_eq!(parser.state, ParseState::Done);
+    assert_eq!(parser.request.path, "/");
+}
+
+#[test]
+fn nul_in_path_or_header_is_rejected() {
+    let parser = parse_line("/files/secret.txt%00.png", None);
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 400));
+    let parser = parse(b"GET / HTTP/1.1\r\nX-Name: a\0b\r\n\r\n", None);
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 400));
+    // An escaped percent sign is not a NUL
+    assert_eq!(parse_line("/files/100%2500", None).state, ParseState::Done);
+}
+
+#[test]
+fn folded_header_line_is_rejected() {
+    for fold in [&b" continued"[..], b"\tcontinued"] {
+        let mut input = b"GET / HTTP/1.1\r\nX-Long: first part\r\n".to_vec();
+        input.extend_from_slice(fold);
+        input.extend_from_slice(b"\r\nHost: example.test\r\n\r\n");
+        let parser = parse(&input, None);
+        assert_eq!((parser.state, parser.error_status), (ParseState::Error, 400));
+    }
+}
+
+#[test]
+fn http2_preface_gets_505() {
+    let parser = parse(b"PRI * HTTP/2.0\r\n\r\nSM\r\n\r\n", None);
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 505));
+    let parser = parse(b"GET / HTTP/3\r\n\r\n", None);
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 505));
+}
+
+#[test]
+fn accept_entries_come_highest_q_first() {
+    assert_eq!(
+        parse_accept("gzip;q=0.5, br;q=1.0, deflate"),
+        [("br".to_string(), 1.0), ("deflate".to_string(), 1.0), ("gzip".to_string(), 0.5)]
+    );
+    // Malformed or out-of-range q-values drop the entry rather than guess
+    assert_eq!(parse_accept("gzip;q=2, br;q=abc, identity; q=0.1 ,"), [("identity".to_string(), 0.1)]);
+}
+
+#[test]
+fn q_zero_is_kept_as_an_explicit_refusal() {
+    assert_eq!(parse_accept("gzip;q=0, *"), [("*".to_string(), 1.0), ("gzip".to_string(), 0.0)]);
+    assert_eq!(media_quality("text/html;q=0, */*", "text/html"), 0.0);
+}
+
+#[test]
+fn most_specific_media_range_wins() {
+    let accept = "*/*;q=0.1, text/*;q=0.5, text/html";
+    assert_eq!(media_quality(accept, "text/html"), 1.0);
+    assert_eq!(media_quality(accept, "text/plain"), 0.5);
+    assert_eq!(media_quality(accept, "image/png"), 0.1);
+    assert_eq!(media_quality("text/html", "application/json"), 0.0);
+}
+
+#[test]
+fn content_length_tolerates_padding_and_a_plus_sign() {
+    for value in [" 10", "+10", "10\t"] {
+        let parser = parse(format!("POST / HTTP/1.1\r\nContent-Length:{}\r\n\r\n0123456789", value).as_bytes(), None);
+        assert_eq!(parser.state, ParseState::Done, "{:?}", value);
+        assert_eq!(parser.request.body, b"0123456789");
+    }
+    for value in ["1 0", "-10", "+", "ten", "+-1"] {
+        let parser = parse(format!("POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n0123456789", value).as_bytes(), None);
+        assert_eq!((parser.state, parser.error_status), (ParseState::Error, 400), "{:?}", value);
+    }
+}
+
+#[test]
+fn unknown_expectation_gets_417() {
+    let parser = parse(b"POST / HTTP/1.1\r\nExpect: foo\r\nContent-Length: 2\r\n\r\nhi", None);
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 417));
+    assert!(Response::new(417).to_bytes().starts_with(b"HTTP/1.1 417 Expectation Failed\r\n"));
+    // 100-continue is understood, in any case
+    let parser = parse(b"POST / HTTP/1.1\r\nExpect: 100-Continue\r\nContent-Length: 2\r\n\r\nhi", None);
+    assert_eq!(parser.state, ParseState::Done);
+}
+
+#[test]
+fn lenient_parsing_folds_only_known_methods() {
+    assert_eq!(Method::parse("get", true).as_str(), "GET");
+    assert_eq!(Method::parse("Delete", true).as_str(), "DELETE");
+    assert_eq!(Method::parse("get", false).as_str(), "get");
+    assert_eq!(Method::parse("patch", true).as_str(), "patch");
+}
+
+#[test]
+fn duplicate_slashes_and_dot_segments_are_normalized() {
+    assert_eq!(normalize_path("/a//b/./c/").as_deref(), Some("/a/b/c/"));
+    assert_eq!(normalize_path("///a///b").as_deref(), Some("/a/b"));
+    assert_eq!(normalize_path("/a/b/../c").as_deref(), Some("/a/c"));
+    assert_eq!(normalize_path("/a/b/..").as_deref(), Some("/a/"));
+    assert_eq!(normalize_path("/a/.").as_deref(), Some("/a/"));
+    assert_eq!(normalize_path("/").as_deref(), Some("/"));
+    assert_eq!(normalize_path("*").as_deref(), Some("*"));
+    // Applied to the decoded target before routing
+    assert_eq!(parse_line("/static//img/./%2E%2E/css/site.css", None).request.path, "/static/css/site.css");
+}
+
+#[test]
+fn traversal_above_the_root_is_rejected() {
+    assert_eq!(normalize_path("/.."), None);
+    assert_eq!(normalize_path("/a/../../etc/passwd"), None);
+    assert_eq!(normalize_path("/a/./../b").as_deref(), Some("/b"));
+    for target in ["/../etc/passwd", "/files/%2e%2e/%2e%2e/etc/passwd", "/a//..//..//etc"] {
+        let parser = parse_line(target, None);
+        assert_eq!((parser.state, parser.error_status), (ParseState::Error, 400), "{}", target);
+    }
+}
+
+#[test]
+fn framing_headers_match_in_any_case() {
+    for name in ["expect", "EXPECT", "eXpEcT"] {
+        let parser = parse(format!("POST / HTTP/1.1\r\n{}: foo\r\nContent-Length: 2\r\n\r\nhi", name).as_bytes(), None);
+        assert_eq!((parser.state, parser.error_status), (ParseState::Error, 417), "{}", name);
+    }
+    for name in ["content-length", "CONTENT-LENGTH"] {
+        // The body must be consumed here, not parsed as the next request
+        let mut parser = Parser::new();
+        parser.parse(format!("POST / HTTP/1.1\r\n{}: 24\r\n\r\nGET /smuggled HTTP/1.1\r\n", name).as_bytes());
+        assert_eq!(parser.state, ParseState::Done, "{}", name);
+        assert_eq!(parser.request.body, b"GET /smuggled HTTP/1.1\r\n");
+        assert_eq!(parser.content_length(), Some(24));
+        assert_eq!(parser.request.headers.get("Content-Length").map(String::as_str), Some("24"));
+    }
+    let parser = parse(b"POST / HTTP/1.1\r\ntransfer-encoding: chunked\r\n\r\n2\r\nhi\r\n0\r\n\r\n", None);
+    assert_eq!((parser.state, parser.request.body.as_slice()), (ParseState::Done, &b"hi"[..]));
+}
+
+#[test]
+fn conflicting_content_lengths_are_rejected() {
+    let parser = parse(b"POST / HTTP/1.1\r\nContent-Length: 2\r\ncontent-length: 24\r\n\r\nhi", None);
+    assert_eq!((parser.state, parser.error_status), (ParseState::Error, 400));
+    // A repeated identical value is harmless
+    let parser = parse(b"POST / HTTP/1.1\r\nContent-Length: 2\r\ncontent-length: 2\r\n\r\nhi", None);
+    assert_eq!(parser.state, ParseState::Done);
+}
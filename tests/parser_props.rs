@@ -0,0 +1,164 @@
+use proptest::prelude::*;
+use rust_localserver::http::{ParseState, Parser};
+
+#[derive(Debug, Clone)]
+enum Body {
+    None,
+    Sized(Vec<u8>),
+    // Each chunk with an optional extension on its size line
+    Chunked(Vec<(Vec<u8>, Option<String>)>),
+}
+
+fn method() -> impl Strategy<Value = &'static str> {
+    prop::sample::select(vec!["GET", "POST", "PUT", "DELETE", "OPTIONS"])
+}
+
+fn path() -> impl Strategy<Value = String> {
+    // Segments can't be `.` or `..`, which normalization resolves or rejects
+    prop::collection::vec("[a-z0-9_-][a-z0-9_.-]{0,7}", 0..4).prop_map(|segments| format!("/{}", segments.join("/")))
+}
+
+fn header() -> impl Strategy<Value = (String, String)> {
+    // Names avoid the framing headers, which the body strategy sets itself
+    ("X-[A-Za-z]{1,10}", "[ -~]{0,20}").prop_map(|(name, value)| (name, value.trim().to_string()))
+}
+
+fn body() -> impl Strategy<Value = Body> {
+    prop_oneof![
+        Just(Body::None),
+        prop::collection::vec(any::<u8>(), 0..256).prop_map(Body::Sized),
+        prop::collection::vec((prop::collection::vec(any::<u8>(), 1..64), prop::option::of(";[a-z]{1,8}(=[a-z0-9]{1,8})?")), 0..6)
+            .prop_map(Body::Chunked),
+    ]
+}
+
+fn request() -> impl Strategy<Value = Vec<u8>> {
+    (method(), path(), prop::collection::vec(header(), 0..6), body()).prop_map(|(method, path, headers, body)| {
+        let mut req = format!("{} {} HTTP/1.1\r\n", method, path).into_bytes();
+        for (name, value) in headers {
+            req.extend_from_slice(format!("{}: {}\r\n", name, value).as_bytes());
+        }
+        match body {
+            Body::None => req.extend_from_slice(b"\r\n"),
+            Body::Sized(data) => {
+                req.extend_from_slice(format!("Content-Length: {}\r\n\r\n", data.len()).as_bytes());
+                req.extend_from_slice(&data);
+            }
+            Body::Chunked(chunks) => {
+                req.extend_from_slice(b"Transfer-Encoding: chunked\r\n\r\n");
+                for (chunk, extension) in chunks {
+                    req.extend_from_slice(format!("{:x}{}\r\n", chunk.len(), extension.unwrap_or_default()).as_bytes());
+                    req.extend_from_slice(&chunk);
+                    req.extend_from_slice(b"\r\n");
+                }
+                req.extend_from_slice(b"0\r\n\r\n");
+            }
+        }
+        req
+    })
+}
+
+fn parse_whole(input: &[u8]) -> Parser {
+    parse_whole_limited(input, None)
+}
+
+fn parse_whole_limited(input: &[u8], max_uri_length: Option<usize>) -> Parser {
+    let mut parser = Parser::new();
+    parser.max_uri_length = max_uri_length;
+    parser.parse(input);
+    parser
+}
+
+fn parse_split(input: &[u8], cuts: &[usize]) -> Parser {
+    parse_split_limited(input, cuts, None)
+}
+
+// Feeds `input` in pieces cut at the given offsets
+fn parse_split_limited(input: &[u8], cuts: &[usize], max_uri_length: Option<usize>) -> Parser {
+    let mut offsets: Vec<usize> = cuts.iter().map(|c| c % (input.len() + 1)).collect();
+    offsets.sort_unstable();
+    let mut parser = Parser::new();
+    parser.max_uri_length = max_uri_length;
+    let mut start = 0;
+    for end in offsets.into_iter().chain([input.len()]) {
+        parser.parse(&input[start..end]);
+        start = end;
+    }
+    parser
+}
+
+#[test]
+fn chunk_size_lines_fed_a_byte_at_a_time() {
+    let input = b"POST /hook HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n5;sig=abc\r\nhello\r\n6 ; last\r\n world\r\n0;done=1\r\n\r\n";
+    let mut parser = Parser::new();
+    for byte in input.iter() {
+        // Until the last byte arrives the parser must neither finish nor give up
+        assert!(!matches!(parser.state, ParseState::Error | ParseState::Done), "{:?}", parser.state);
+        parser.parse(std::slice::from_ref(byte));
+    }
+    assert_eq!(parser.state, ParseState::Done);
+    assert_eq!(parser.request.body, b"hello world");
+    // The same bytes in one read agree
+    assert_eq!(parse_whole(input).request.body, b"hello world");
+}
+
+proptest! {
+    #[test]
+    fn well_formed_requests_parse_the_same_however_split(
+        input in request(),
+        cuts in prop::collection::vec(any::<usize>(), 0..16),
+    ) {
+        let whole = parse_whole(&input);
+        prop_assert_eq!(&whole.state, &ParseState::Done);
+
+        let split = parse_split(&input, &cuts);
+        prop_assert_eq!(&split.state, &ParseState::Done);
+        prop_assert_eq!(whole.request.method.as_str(), split.request.method.as_str());
+        prop_assert_eq!(&whole.request.path, &split.request.path);
+        prop_assert_eq!(&whole.request.headers, &split.request.headers);
+        prop_assert_eq!(&whole.request.body, &split.request.body);
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic_and_split_consistently(
+        input in prop::collection::vec(any::<u8>(), 0..512),
+        cuts in prop::collection::vec(any::<usize>(), 0..16),
+    ) {
+        let whole = parse_whole(&input);
+        let split = parse_split(&input, &cuts);
+        prop_assert_eq!(&whole.state, &split.state);
+        if whole.state == ParseState::Error {
+            prop_assert_eq!(whole.error_status, split.error_status);
+        }
+    }
+
+    #[test]
+    fn uri_limit_is_split_invariant(
+        method in "[A-Z]{1,80}",
+        target in "/[a-z%0-9]{0,120}",
+        max in 8usize..64,
+        cuts in prop::collection::vec(any::<usize>(), 0..16),
+    ) {
+        let input = format!("{} {} HTTP/1.1\r\n\r\n", method, target).into_bytes();
+        let whole = parse_whole_limited(&input, Some(max));
+        let split = parse_split_limited(&input, &cuts, Some(max));
+        prop_assert_eq!(&whole.state, &split.state);
+        prop_assert_eq!(whole.error_status, split.error_status);
+    }
+
+    #[test]
+    fn corrupted_requests_never_panic(
+        input in request(),
+        flips in prop::collection::vec((any::<usize>(), any::<u8>()), 1..8),
+        cuts in prop::collection::vec(any::<usize>(), 0..16),
+    ) {
+        let mut input = input;
+        for (at, byte) in flips {
+            let at = at % input.len();
+            input[at] = byte;
+        }
+        let whole = parse_whole(&input);
+        let split = parse_split(&input, &cuts);
+        prop_assert_eq!(&whole.state, &split.state);
+    }
+}
@@ -0,0 +1,843 @@
+use rust_localserver::config::Config;
+use rust_localserver::http::{Method, Request, Response, CLOSE_WITHOUT_RESPONSE};
+use rust_localserver::router::Router;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_SITE: AtomicUsize = AtomicUsize::new(0);
+
+/// Scratch directory removed on drop.
+struct Site(PathBuf);
+
+impl Drop for Site {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn scratch() -> Site {
+    let dir = Site(std::env::temp_dir().join(format!(
+        "rust-localserver-router-{}-{}",
+        std::process::id(),
+        NEXT_SITE.fetch_add(1, Ordering::SeqCst)
+    )));
+    fs::create_dir_all(&dir.0).unwrap();
+    dir
+}
+
+// One server on port 8080; `{root}` in the YAML stands for a fresh scratch directory
+fn site(yaml: &str) -> (Router, Site) {
+    let dir = scratch();
+    let yaml = yaml.replace("{root}", &dir.0.display().to_string());
+    (Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap()), dir)
+}
+
+fn send(router: &Router, method: &str, path: &str, headers: &[(&str, &str)], body: &[u8]) -> Response {
+    let mut request = Request::new();
+    request.method = Method::from(method);
+    request.path = path.to_string();
+    for (name, value) in headers {
+        request.headers.insert(name.to_string(), value.to_string());
+    }
+    request.body = body.to_vec();
+    router.handle(&request, 0)
+}
+
+// Two named servers sharing port 8080, each serving a directory that names it
+fn router(globals: &str, default_server: Option<usize>) -> (Router, Site) {
+    let dir = scratch();
+    let mut yaml = format!("{}servers:\n", globals);
+    for (i, name) in ["alpha.test", "beta.test"].iter().enumerate() {
+        let root = dir.0.join(name);
+        fs::create_dir_all(&root).unwrap();
+        fs::write(root.join("who.txt"), name).unwrap();
+        yaml.push_str(&format!(
+            "  - host: \"127.0.0.1\"\n    ports: [8080]\n    server_names: [\"{}\"]\n    default_server: {}\n    routes:\n      - path: \"/\"\n        root: \"{}\"\n",
+            name,
+            default_server == Some(i),
+            root.display()
+        ));
+    }
+    let config: Config = serde_yaml::from_str(&yaml).unwrap();
+    (Router::new(config), dir)
+}
+
+fn get(router: &Router, host: &str) -> (u16, Vec<u8>) {
+    let mut request = Request::new();
+    request.path = "/who.txt".to_string();
+    request.headers.insert("Host".to_string(), host.to_string());
+    let res = router.handle(&request, 0);
+    (res.status_code, res.body)
+}
+
+#[test]
+fn matching_host_picks_its_server() {
+    let (router, _dir) = router("no_host_match: not_found\n", None);
+    assert_eq!(get(&router, "beta.test"), (200, b"beta.test".to_vec()));
+}
+
+#[test]
+fn unmatched_host_uses_first_server_by_default() {
+    let (router, _dir) = router("", None);
+    assert_eq!(get(&router, "10.0.0.1"), (200, b"alpha.test".to_vec()));
+}
+
+#[test]
+fn unmatched_host_gets_404_with_not_found() {
+    let (router, _dir) = router("no_host_match: not_found\n", None);
+    assert_eq!(get(&router, "10.0.0.1").0, 404);
+}
+
+#[test]
+fn unmatched_host_is_dropped_with_close() {
+    let (router, _dir) = router("no_host_match: close\n", None);
+    assert_eq!(get(&router, "10.0.0.1").0, CLOSE_WITHOUT_RESPONSE);
+}
+
+#[test]
+fn unmatched_host_goes_to_default_server() {
+    // The marked default wins over no_host_match
+    let (router, _dir) = router("no_host_match: close\n", Some(1));
+    assert_eq!(get(&router, "10.0.0.1"), (200, b"beta.test".to_vec()));
+}
+
+fn refused(server_yaml: &str, method: &str, target: &str) -> (u16, Option<String>) {
+    let yaml = format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}    routes:\n      - path: \"/\"\n        root: \"/tmp\"\n        methods: [\"GET\"]\n", server_yaml);
+    let router = Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap());
+    let mut request = Request::new();
+    request.method = Method::from(method);
+    request.path = target.to_string();
+    let res = router.handle(&request, 0);
+    (res.status_code, res.headers.get("Allow").cloned())
+}
+
+#[test]
+fn connect_and_trace_get_405_by_default() {
+    assert_eq!(refused("", "CONNECT", "example.com:443"), (405, Some("GET, HEAD, POST, OPTIONS".to_string())));
+    assert_eq!(refused("", "TRACE", "/"), (405, Some("GET".to_string())));
+}
+
+#[test]
+fn connect_gets_configured_status() {
+    assert_eq!(refused("    trace_connect_status: 501\n", "CONNECT", "example.com:443"), (501, None));
+    assert_eq!(refused("    trace_connect_status: 501\n", "TRACE", "/"), (501, None));
+}
+
+fn not_found(server_yaml: &str, accept: &str) -> (String, Vec<u8>) {
+    let yaml = format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}    routes:\n      - path: \"/api\"\n        root: \"/nonexistent\"\n", server_yaml);
+    let router = Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap());
+    let mut request = Request::new();
+    request.path = "/api/missing".to_string();
+    request.headers.insert("Accept".to_string(), accept.to_string());
+    let res = router.handle(&request, 0);
+    assert_eq!(res.status_code, 404);
+    (res.headers["Content-Type"].clone(), res.body)
+}
+
+#[test]
+fn json_errors_follow_accept() {
+    let (content_type, body) = not_found("    json_errors: true\n", "application/json");
+    assert_eq!(content_type, "application/json");
+    assert_eq!(body, br#"{"status":404,"error":"Not Found"}"#);
+    // Browsers still get HTML
+    let (content_type, _) = not_found("    json_errors: true\n", "text/html,application/xhtml+xml,*/*;q=0.8");
+    assert_eq!(content_type, "text/html");
+}
+
+#[test]
+fn json_errors_are_off_by_default() {
+    assert_eq!(not_found("", "application/json").0, "text/html");
+}
+
+const PUT_SITE: &str = "servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n        methods: [\"GET\", \"PUT\"]\n        allow_uploads: true\n";
+
+#[test]
+fn contiguous_content_range_puts_assemble_the_file() {
+    let (router, dir) = site(PUT_SITE);
+    let first = send(&router, "PUT", "/big.bin", &[("Content-Range", "bytes 0-4/10")], b"hello");
+    assert_eq!(first.status_code, 201);
+    let second = send(&router, "PUT", "/big.bin", &[("Content-Range", "bytes 5-9/10")], b"world");
+    assert_eq!(second.status_code, 204);
+    assert_eq!(fs::read(dir.0.join("big.bin")).unwrap(), b"helloworld");
+    assert_eq!(send(&router, "GET", "/big.bin", &[], b"").body, b"helloworld");
+}
+
+#[test]
+fn content_range_must_match_the_body() {
+    let (router, _dir) = site(PUT_SITE);
+    assert_eq!(send(&router, "PUT", "/big.bin", &[("Content-Range", "bytes 0-9/10")], b"hello").status_code, 400);
+}
+
+#[test]
+fn content_range_starting_past_the_end_of_the_file_gets_416() {
+    let (router, dir) = site(PUT_SITE);
+    assert_eq!(send(&router, "PUT", "/big.bin", &[("Content-Range", "bytes 0-4/20")], b"hello").status_code, 201);
+    // Skipping bytes 5-9 would leave a zero-filled hole
+    let gap = send(&router, "PUT", "/big.bin", &[("Content-Range", "bytes 10-14/20")], b"world");
+    assert_eq!(gap.status_code, 416);
+    assert_eq!(gap.headers.get("Content-Range").map(String::as_str), Some("bytes */5"));
+    let huge = send(&router, "PUT", "/huge.bin", &[("Content-Range", "bytes 1099511627770-1099511627775/1099511627776")], b"sparse");
+    assert_eq!(huge.status_code, 416);
+    assert!(!dir.0.join("huge.bin").exists());
+    assert_eq!(fs::metadata(dir.0.join("big.bin")).unwrap().len(), 5);
+}
+
+#[test]
+fn content_range_total_must_be_known_and_within_the_body_limit() {
+    let (router, dir) = site(&PUT_SITE.replace("    routes:\n", "    client_max_body_size: 1024\n    routes:\n"));
+    let put = |range: &str| send(&router, "PUT", "/big.bin", &[("Content-Range", range)], b"hello").status_code;
+    assert_eq!(put("bytes 1099511627770-1099511627774/*"), 400);
+    assert_eq!(put("bytes 0-4/*"), 400);
+    assert_eq!(put("bytes 0-4/1099511627776"), 413);
+    assert!(!dir.0.join("big.bin").exists());
+    assert_eq!(put("bytes 0-4/1024"), 201);
+}
+
+#[test]
+fn bodiless_created_response_carries_content_length() {
+    let (router, _dir) = site(PUT_SITE);
+    let bytes = send(&router, "PUT", "/new.txt", &[], b"x").to_bytes();
+    let head = String::from_utf8_lossy(&bytes);
+    assert!(head.starts_with("HTTP/1.1 201"), "{}", head);
+    assert!(head.contains("Content-Length: 0\r\n"), "{}", head);
+}
+
+// `script` saved as run.sh under a /bin/sh CGI route; `globals` and `route` add YAML keys
+fn cgi_site(globals: &str, route: &str, script: &str) -> (Router, Site) {
+    let (router, dir) = site(&format!(
+        "{}servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{{root}}\"\n        cgi_extensions: {{\".sh\": \"/bin/sh\"}}\n{}",
+        globals, route
+    ));
+    fs::write(dir.0.join("run.sh"), script).unwrap();
+    (router, dir)
+}
+
+#[test]
+fn cgi_with_too_many_headers_gets_502() {
+    let script = "for i in 1 2 3 4 5; do echo \"X-Header-$i: value\"; done\necho\necho body\n";
+    let (router, _dir) = cgi_site("max_response_headers: 3\n", "", script);
+    let res = send(&router, "GET", "/run.sh", &[], b"");
+    assert_eq!(res.status_code, 502);
+    assert!(String::from_utf8_lossy(&res.to_bytes()).contains(&format!("Content-Length: {}\r\n", res.body.len())));
+
+    let (router, _dir) = cgi_site("max_response_headers: 5\n", "", script);
+    let res = send(&router, "GET", "/run.sh", &[], b"");
+    assert_eq!((res.status_code, res.body), (200, b"body\n".to_vec()));
+}
+
+#[test]
+fn cgi_output_over_the_limit_gets_502() {
+    let (router, _dir) = cgi_site("", "        cgi_max_output: 1024\n", "head -c 100000 /dev/zero\n");
+    let res = send(&router, "GET", "/run.sh", &[], b"");
+    assert_eq!(res.status_code, 502);
+    assert_eq!(res.body, b"CGI Error: output exceeded 1024 bytes");
+}
+
+#[test]
+fn cgi_with_noisy_stderr_still_completes() {
+    // Far more than a pipe buffer holds, written before any stdout
+    let script = "head -c 204800 /dev/zero >&2\necho \"Content-Type: text/plain\"\necho\necho done\n";
+    let (router, _dir) = cgi_site("", "", script);
+    let res = send(&router, "GET", "/run.sh", &[], b"");
+    assert_eq!((res.status_code, res.body), (200, b"done\n".to_vec()));
+}
+
+const DEFAULT_METHODS_SITE: &str = "servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n";
+
+#[test]
+fn default_methods_leave_out_writes() {
+    let (router, dir) = site(DEFAULT_METHODS_SITE);
+    fs::write(dir.0.join("keep.txt"), "kept").unwrap();
+    for method in ["PUT", "DELETE"] {
+        let res = send(&router, method, "/keep.txt", &[], b"gone");
+        assert_eq!(res.status_code, 405, "{}", method);
+        assert_eq!(res.headers.get("Allow").map(String::as_str), Some("GET, HEAD, POST, OPTIONS"));
+    }
+    assert_eq!(fs::read(dir.0.join("keep.txt")).unwrap(), b"kept");
+}
+
+#[test]
+fn registered_method_gets_405_and_unknown_one_501() {
+    let (router, _dir) = site(DEFAULT_METHODS_SITE);
+    let res = send(&router, "LOCK", "/file.txt", &[], b"");
+    assert_eq!(res.status_code, 405);
+    assert_eq!(res.headers.get("Allow").map(String::as_str), Some("GET, HEAD, POST, OPTIONS"));
+    assert_eq!(send(&router, "FOOBAR", "/file.txt", &[], b"").status_code, 501);
+}
+
+#[cfg(unix)]
+#[test]
+fn put_through_symlink_to_outside_the_root_is_refused() {
+    let (router, dir) = site(PUT_SITE);
+    let outside = scratch();
+    std::os::unix::fs::symlink(&outside.0, dir.0.join("escape")).unwrap();
+    // Neither the file nor its new parent exist yet
+    assert_eq!(send(&router, "PUT", "/escape/new/file.txt", &[], b"x").status_code, 403);
+    assert!(!outside.0.join("new").exists());
+    // A dangling link can't be checked either
+    std::os::unix::fs::symlink(outside.0.join("missing"), dir.0.join("dangling")).unwrap();
+    assert_eq!(send(&router, "PUT", "/dangling/file.txt", &[], b"x").status_code, 403);
+    assert!(!outside.0.join("missing").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn files_picked_after_the_path_check_are_confined_too() {
+    let options = "root: \"{root}\"\n        index: \"index.html\"\n        negotiate: true\n        brotli_static: true\n        not_found_fallback: \"app.html\"\n";
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("root: \"{root}\"\n", options));
+    let outside = scratch();
+    fs::write(outside.0.join("secret"), "secret").unwrap();
+    let link = |name: &str| std::os::unix::fs::symlink(outside.0.join("secret"), dir.0.join(name)).unwrap();
+    fs::create_dir_all(dir.0.join("docs/deep")).unwrap();
+    link("docs/index.html");
+    fs::write(dir.0.join("site.js"), "plain").unwrap();
+    link("site.js.br");
+    link("data.json");
+    link("docs/deep/app.html");
+
+    let get = |path: &str, headers: &[(&str, &str)]| send(&router, "GET", path, headers, b"");
+    assert_eq!(get("/docs/", &[]).status_code, 403);
+    assert_eq!(get("/site.js", &[("Accept-Encoding", "br")]).status_code, 403);
+    assert_eq!(get("/data", &[("Accept", "application/json")]).status_code, 403);
+    let fallback = get("/docs/deep/missing", &[]);
+    assert_eq!(fallback.status_code, 404);
+    assert_ne!(fallback.body, b"secret");
+    // A link that stays inside the root is still followed
+    std::os::unix::fs::symlink(dir.0.join("site.js"), dir.0.join("alias.js")).unwrap();
+    assert_eq!(get("/alias.js", &[]).body, b"plain");
+}
+
+#[cfg(unix)]
+#[test]
+fn root_created_after_startup_is_still_confined() {
+    let base = scratch();
+    let root = base.0.join("later");
+    let yaml = PUT_SITE.replace("{root}", &root.display().to_string());
+    let router = Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap());
+    // Written through the missing root, then checked once it exists
+    assert_eq!(send(&router, "PUT", "/a/b.txt", &[], b"x").status_code, 201);
+    assert_eq!(fs::read(root.join("a/b.txt")).unwrap(), b"x");
+
+    let outside = scratch();
+    fs::write(outside.0.join("secret.txt"), "secret").unwrap();
+    std::os::unix::fs::symlink(&outside.0, root.join("escape")).unwrap();
+    assert_eq!(send(&router, "GET", "/escape/secret.txt", &[], b"").status_code, 403);
+    assert_eq!(send(&router, "PUT", "/escape/secret.txt", &[], b"x").status_code, 403);
+    assert_eq!(fs::read(outside.0.join("secret.txt")).unwrap(), b"secret");
+}
+
+fn host_status(host: &str) -> u16 {
+    let (router, _dir) = site(DEFAULT_METHODS_SITE);
+    send(&router, "GET", "/missing.txt", &[("Host", host)], b"").status_code
+}
+
+#[test]
+fn reg_name_hosts_are_accepted() {
+    for host in ["my_service", "my_service:8080", "a~b.example", "x!$&'()*+,;=y", "caf%C3%A9.example", "[::1]:8080", "10.0.0.1"] {
+        assert_eq!(host_status(host), 404, "{}", host);
+    }
+}
+
+#[test]
+fn malformed_hosts_get_400() {
+    for host in ["user@example.com", "example.com/foo", "example.com?q", "example.com#frag", "exa mple.com", "example.com:", "example.com:http", "example.com:123456", "%zz.example", "[::1", "[::1]x", ":8080"] {
+        assert_eq!(host_status(host), 400, "{}", host);
+    }
+}
+
+#[test]
+fn bad_host_400_uses_the_selected_servers_error_page() {
+    let yaml = "servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    server_names: [\"alpha.test\"]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    server_names: [\"beta.test\"]\n    json_errors: true\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n";
+    let (router, _dir) = site(yaml);
+    let res = send(&router, "GET", "/", &[("Host", "beta.test/foo"), ("Accept", "application/json")], b"");
+    assert_eq!(res.status_code, 400);
+    assert_eq!(res.body, br#"{"status":400,"error":"Bad Request"}"#);
+}
+
+#[test]
+fn body_over_client_max_body_size_gets_413() {
+    let yaml = PUT_SITE.replace("    routes:\n", "    client_max_body_size: 4\n    routes:\n");
+    let (router, dir) = site(&yaml);
+    assert_eq!(send(&router, "PUT", "/a.txt", &[], b"12345").status_code, 413);
+    assert!(!dir.0.join("a.txt").exists());
+    assert_eq!(send(&router, "PUT", "/a.txt", &[], b"1234").status_code, 201);
+}
+
+#[test]
+fn options_on_patch_route_advertises_accept_patch() {
+    let (router, _dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/doc\"\n        root: \"{root}\"\n        methods: [\"GET\", \"PATCH\"]\n        accept_patch: [\"application/json-patch+json\", \"application/merge-patch+json\"]\n      - path: \"/raw\"\n        root: \"{root}\"\n        methods: [\"PATCH\"]\n      - path: \"/\"\n        root: \"{root}\"\n        methods: [\"GET\"]\n");
+    let res = send(&router, "OPTIONS", "/doc", &[], b"");
+    assert_eq!(res.status_code, 204);
+    assert_eq!(res.headers.get("Allow").map(String::as_str), Some("GET, PATCH, OPTIONS"));
+    assert_eq!(res.headers.get("Accept-Patch").map(String::as_str), Some("application/json-patch+json, application/merge-patch+json"));
+
+    let res = send(&router, "OPTIONS", "/raw", &[], b"");
+    assert_eq!(res.headers.get("Accept-Patch").map(String::as_str), Some("application/octet-stream"));
+    // Nothing to advertise without PATCH
+    assert_eq!(send(&router, "OPTIONS", "/", &[], b"").headers.get("Accept-Patch"), None);
+}
+
+fn fetch_encoded(router: &Router, path: &str, accept_encoding: Option<&str>) -> Response {
+    let headers: Vec<(&str, &str)> = accept_encoding.map(|value| ("Accept-Encoding", value)).into_iter().collect();
+    send(router, "GET", path, &headers, b"")
+}
+
+#[test]
+fn brotli_sibling_is_preferred_when_accepted() {
+    let (router, dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n        brotli_static: true\n");
+    fs::write(dir.0.join("app.js"), "plain").unwrap();
+    fs::write(dir.0.join("app.js.br"), "brotli").unwrap();
+    fs::write(dir.0.join("app.js.gz"), "gzip").unwrap();
+
+    let res = fetch_encoded(&router, "/app.js", Some("gzip, deflate, br"));
+    assert_eq!(res.body, b"brotli");
+    assert_eq!(res.headers.get("Content-Encoding").map(String::as_str), Some("br"));
+    assert_eq!(res.headers.get("Content-Type").map(String::as_str), Some("application/javascript"));
+    assert_eq!(res.headers.get("Vary").map(String::as_str), Some("Accept-Encoding"));
+
+    for accept in [Some("gzip"), Some("br;q=0, gzip"), None] {
+        let res = fetch_encoded(&router, "/app.js", accept);
+        assert_eq!(res.body, b"plain", "{:?}", accept);
+        assert_eq!(res.headers.get("Content-Encoding"), None);
+    }
+    // No sibling to prefer
+    fs::write(dir.0.join("other.js"), "plain").unwrap();
+    assert_eq!(fetch_encoded(&router, "/other.js", Some("br")).body, b"plain");
+}
+
+#[test]
+fn brotli_static_is_off_by_default() {
+    let (router, dir) = site(DEFAULT_METHODS_SITE);
+    fs::write(dir.0.join("app.js"), "plain").unwrap();
+    fs::write(dir.0.join("app.js.br"), "brotli").unwrap();
+    assert_eq!(fetch_encoded(&router, "/app.js", Some("br")).body, b"plain");
+}
+
+#[test]
+fn cache_control_follows_the_extension() {
+    let (router, dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n        cache_control:\n          \".js\": \"max-age=31536000, immutable\"\n          \".html\": \"no-cache\"\n          \"*\": \"max-age=60\"\n");
+    for name in ["app.3f2a.js", "index.html", "logo.png"] {
+        fs::write(dir.0.join(name), name).unwrap();
+    }
+    let cache_control = |path: &str| send(&router, "GET", path, &[], b"").headers.get("Cache-Control").cloned();
+    assert_eq!(cache_control("/app.3f2a.js").as_deref(), Some("max-age=31536000, immutable"));
+    assert_eq!(cache_control("/index.html").as_deref(), Some("no-cache"));
+    assert_eq!(cache_control("/logo.png").as_deref(), Some("max-age=60"));
+    // Only successful static responses are cacheable this way
+    assert_eq!(cache_control("/missing.js"), None);
+}
+
+#[test]
+fn verbose_errors_echo_the_escaped_request() {
+    let path = "/<script>alert(1)</script>";
+    let (router, _dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    verbose_errors: true\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n");
+    let body = String::from_utf8(send(&router, "GET", path, &[], b"").body).unwrap();
+    assert!(body.contains("<p>GET /&lt;script&gt;alert(1)&lt;/script&gt;</p>"), "{}", body);
+    assert!(!body.contains("<script>"), "{}", body);
+
+    let (router, _dir) = site(DEFAULT_METHODS_SITE);
+    let body = String::from_utf8(send(&router, "GET", path, &[], b"").body).unwrap();
+    assert!(!body.contains("script"), "{}", body);
+}
+
+fn listing_order(router: &Router, query: Option<&str>) -> Vec<String> {
+    let mut request = Request::new();
+    request.path = "/".to_string();
+    request.query = query.map(str::to_string);
+    let html = String::from_utf8(router.handle(&request, 0).body).unwrap();
+    html.split("\">").skip(1).map(|rest| rest.split('<').next().unwrap().to_string()).collect()
+}
+
+#[test]
+fn autoindex_sorts_by_configured_order_and_query() {
+    let (router, dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n        autoindex: true\n        autoindex_sort: size_desc\n");
+    for (name, size) in [("b.txt", 10), ("a.txt", 3), ("c.txt", 1)] {
+        fs::write(dir.0.join(name), "x".repeat(size)).unwrap();
+    }
+    assert_eq!(listing_order(&router, None), ["b.txt", "a.txt", "c.txt"]);
+    // Apache-style query overrides the configured order
+    assert_eq!(listing_order(&router, Some("C=N;O=A")), ["a.txt", "b.txt", "c.txt"]);
+    assert_eq!(listing_order(&router, Some("C=S;O=A")), ["c.txt", "a.txt", "b.txt"]);
+}
+
+const ENV_SCRIPT: &str = "echo \"Content-Type: text/plain\"\necho\nfor name in APP_MODE REDIRECT_STATUS SCRIPT_NAME PATH_INFO PATH_TRANSLATED; do eval \"echo $name=\\$$name\"; done\n";
+
+fn cgi_env(router: &Router, path: &str) -> Vec<String> {
+    let res = send(router, "GET", path, &[], b"");
+    assert_eq!(res.status_code, 200, "{}", String::from_utf8_lossy(&res.body));
+    String::from_utf8(res.body).unwrap().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn route_cgi_env_reaches_the_script() {
+    let route = "        cgi_env:\n          APP_MODE: \"staging\"\n          REDIRECT_STATUS: \"200\"\n          SCRIPT_NAME: \"/spoofed\"\n";
+    let (router, _dir) = cgi_site("", route, ENV_SCRIPT);
+    let env = cgi_env(&router, "/run.sh");
+    assert!(env.contains(&"APP_MODE=staging".to_string()), "{:?}", env);
+    assert!(env.contains(&"REDIRECT_STATUS=200".to_string()), "{:?}", env);
+    // Protocol variables can't be overridden from the route
+    assert!(env.contains(&"SCRIPT_NAME=/run.sh".to_string()), "{:?}", env);
+}
+
+#[test]
+fn extra_path_after_the_script_becomes_path_info() {
+    let (router, dir) = cgi_site("", "", ENV_SCRIPT);
+    let env = cgi_env(&router, "/run.sh/foo/bar");
+    assert!(env.contains(&"SCRIPT_NAME=/run.sh".to_string()), "{:?}", env);
+    assert!(env.contains(&"PATH_INFO=/foo/bar".to_string()), "{:?}", env);
+    assert!(env.contains(&format!("PATH_TRANSLATED={}", dir.0.join("foo/bar").display())), "{:?}", env);
+
+    let env = cgi_env(&router, "/run.sh");
+    assert!(env.contains(&"PATH_INFO=".to_string()), "{:?}", env);
+    assert!(env.contains(&"PATH_TRANSLATED=".to_string()), "{:?}", env);
+}
+
+#[test]
+fn host_variable_picks_a_root_per_host() {
+    let (router, dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}/$host\"\n");
+    for name in ["alpha.test", "beta.test"] {
+        fs::create_dir_all(dir.0.join(name)).unwrap();
+        fs::write(dir.0.join(name).join("who.txt"), name).unwrap();
+    }
+    fs::write(dir.0.join("who.txt"), "parent").unwrap();
+    assert_eq!(get(&router, "alpha.test"), (200, b"alpha.test".to_vec()));
+    // Case and port don't pick a different directory
+    assert_eq!(get(&router, "BETA.test:8080"), (200, b"beta.test".to_vec()));
+    assert_eq!(get(&router, "gamma.test").0, 404);
+    // Nothing climbs out of the templated directory
+    assert_eq!(get(&router, "..").0, 403);
+    assert_eq!(get(&router, ".alpha.test").0, 403);
+}
+
+#[test]
+fn longest_prefix_wins_among_many_routes() {
+    let mut yaml = String::from("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"/srv/root\"\n");
+    for i in 0..1000 {
+        yaml.push_str(&format!("      - path: \"/section{}\"\n        root: \"/srv/{}\"\n      - path: \"/section{}/deep\"\n        root: \"/srv/{}-deep\"\n", i, i, i, i));
+    }
+    let router = Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap());
+    let root = |path: &str| router.find_route(0, path).unwrap().root.clone().unwrap();
+    assert_eq!(root("/section500/deep/a/b.html"), "/srv/500-deep");
+    assert_eq!(root("/section500/other.html"), "/srv/500");
+    // `/section5` is a prefix too; the longer `/section50` wins
+    assert_eq!(root("/section50/index.html"), "/srv/50");
+    assert_eq!(root("/elsewhere"), "/srv/root");
+}
+
+fn query_status(server_yaml: &str, query: &str) -> u16 {
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("    routes:\n", &format!("{}    routes:\n", server_yaml)));
+    fs::write(dir.0.join("search.txt"), "ok").unwrap();
+    let mut request = Request::new();
+    request.path = "/search.txt".to_string();
+    request.query = Some(query.to_string());
+    router.handle(&request, 0).status_code
+}
+
+#[test]
+fn too_many_query_params_get_400() {
+    let limit = "    max_query_params: 3\n";
+    assert_eq!(query_status(limit, "a=1&b=2&c=3"), 200);
+    assert_eq!(query_status(limit, "a=1&b=2&c=3&d=4"), 400);
+    // Empty pairs from stray separators don't count
+    assert_eq!(query_status(limit, "a=1&&b=2&c=3&"), 200);
+    assert_eq!(query_status("", &"p=1&".repeat(10_000)), 200);
+}
+
+#[test]
+fn query_longer_than_max_query_length_gets_400() {
+    let limit = "    max_query_length: 16\n";
+    assert_eq!(query_status(limit, "q=0123456789abcd"), 200);
+    assert_eq!(query_status(limit, "q=0123456789abcde"), 400);
+}
+
+fn unmatched_options(mode: &str) -> Response {
+    let (router, _dir) = site(&format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}    routes:\n      - path: \"/api\"\n        root: \"{{root}}\"\n        methods: [\"GET\", \"PUT\"]\n      - path: \"/static\"\n        root: \"{{root}}\"\n        methods: [\"GET\", \"POST\"]\n", mode));
+    send(&router, "OPTIONS", "/nowhere", &[], b"")
+}
+
+#[test]
+fn unmatched_options_follows_options_unmatched() {
+    for mode in ["", "    options_unmatched: \"404\"\n"] {
+        let res = unmatched_options(mode);
+        assert_eq!((res.status_code, res.headers.get("Allow")), (404, None), "{:?}", mode);
+    }
+    let res = unmatched_options("    options_unmatched: \"405\"\n");
+    assert_eq!(res.status_code, 405);
+    assert_eq!(res.headers.get("Allow").map(String::as_str), Some("GET, PUT, POST, OPTIONS"));
+    let res = unmatched_options("    options_unmatched: allow\n");
+    assert_eq!(res.status_code, 204);
+    assert_eq!(res.headers.get("Allow").map(String::as_str), Some("GET, PUT, POST, OPTIONS"));
+}
+
+fn redirect_location(server_yaml: &str, path: &str) -> Option<String> {
+    let (router, _dir) = site(&format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}    routes:\n      - path: \"/old\"\n        redirect: \"/new\"\n      - path: \"/docs/legacy\"\n        redirect: \"current\"\n      - path: \"/away\"\n        redirect: \"https://elsewhere.test/\"\n", server_yaml));
+    let res = send(&router, "GET", path, &[("Host", "example.test:8080")], b"");
+    assert_eq!(res.status_code, 301);
+    res.headers.get("Location").cloned()
+}
+
+#[test]
+fn relative_redirects_stay_relative_by_default() {
+    assert_eq!(redirect_location("", "/old").as_deref(), Some("/new"));
+    assert_eq!(redirect_location("", "/docs/legacy").as_deref(), Some("current"));
+}
+
+#[test]
+fn absolute_redirects_resolve_against_the_request() {
+    let absolute = "    absolute_redirects: true\n";
+    assert_eq!(redirect_location(absolute, "/old").as_deref(), Some("http://example.test:8080/new"));
+    assert_eq!(redirect_location(absolute, "/docs/legacy").as_deref(), Some("http://example.test:8080/docs/current"));
+    assert_eq!(redirect_location(absolute, "/away").as_deref(), Some("https://elsewhere.test/"));
+}
+
+#[test]
+fn extensionless_path_is_negotiated_by_accept() {
+    let (router, dir) = site("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n    routes:\n      - path: \"/\"\n        root: \"{root}\"\n        negotiate: true\n");
+    fs::write(dir.0.join("data.json"), "{}").unwrap();
+    fs::write(dir.0.join("data.xml"), "<data/>").unwrap();
+    let fetch = |accept: &str| {
+        let res = send(&router, "GET", "/data", &[("Accept", accept)], b"");
+        (res.status_code, res.headers.get("Content-Type").cloned(), res.body)
+    };
+    assert_eq!(fetch("application/xml"), (200, Some("application/xml".to_string()), b"<data/>".to_vec()));
+    assert_eq!(fetch("application/json"), (200, Some("application/json".to_string()), b"{}".to_vec()));
+    assert_eq!(fetch("application/json;q=0.5, application/xml;q=0.9").2, b"<data/>");
+    assert_eq!(fetch("text/csv").0, 404);
+    let res = send(&router, "GET", "/data", &[("Accept", "application/json")], b"");
+    assert_eq!(res.headers.get("Vary").map(String::as_str), Some("Accept"));
+}
+
+#[test]
+fn negotiation_never_picks_a_sidecar_or_unknown_type() {
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("root: \"{root}\"\n", "root: \"{root}\"\n        negotiate: true\n        header_sidecars: true\n"));
+    fs::write(dir.0.join("app.html"), "page").unwrap();
+    fs::write(dir.0.join("app.headers"), "X-Secret: sidecar").unwrap();
+    fs::write(dir.0.join("app.br"), "compressed").unwrap();
+    fs::write(dir.0.join("app.bak"), "backup").unwrap();
+    // All sort before `app.html`, and `*/*` would take any of them on a tie
+    let res = send(&router, "GET", "/app", &[("Accept", "*/*")], b"");
+    assert_eq!((res.status_code, res.body.as_slice()), (200, &b"page"[..]));
+    assert_eq!(send(&router, "GET", "/app", &[], b"").body, b"page");
+    assert_eq!(send(&router, "GET", "/app", &[("Accept", "application/octet-stream")], b"").status_code, 404);
+}
+
+fn slash_root(strict: &str, path: &str) -> String {
+    let yaml = format!("servers:\n  - host: \"127.0.0.1\"\n    ports: [8080]\n{}    routes:\n      - path: \"/\"\n        root: \"/srv/root\"\n      - path: \"/api/\"\n        root: \"/srv/api\"\n      - path: \"/docs\"\n        root: \"/srv/docs\"\n", strict);
+    let router = Router::new(serde_yaml::from_str::<Config>(&yaml).unwrap());
+    router.find_route(0, path).unwrap().root.clone().unwrap()
+}
+
+#[test]
+fn strict_slash_keeps_trailing_slashes_significant() {
+    // The default
+    assert_eq!(slash_root("", "/api"), "/srv/root");
+    assert_eq!(slash_root("", "/api/"), "/srv/api");
+    assert_eq!(slash_root("    strict_slash: true\n", "/api"), "/srv/root");
+    assert_eq!(slash_root("", "/docs/"), "/srv/docs");
+}
+
+#[test]
+fn without_strict_slash_both_spellings_match() {
+    let loose = "    strict_slash: false\n";
+    for path in ["/api", "/api/", "/api/users"] {
+        assert_eq!(slash_root(loose, path), "/srv/api", "{}", path);
+    }
+    for path in ["/docs", "/docs/", "/docs/guide"] {
+        assert_eq!(slash_root(loose, path), "/srv/docs", "{}", path);
+    }
+    assert_eq!(slash_root(loose, "/"), "/srv/root");
+}
+
+fn forwarded_headers(route: &str, headers: &[(String, String)]) -> Vec<String> {
+    let script = "echo \"Content-Type: text/plain\"\necho\nenv | grep '^HTTP_' | sort\n";
+    let (router, _dir) = cgi_site("", route, script);
+    let headers: Vec<(&str, &str)> = headers.iter().map(|(n, v)| (n.as_str(), v.as_str())).collect();
+    let res = send(&router, "GET", "/run.sh", &headers, b"");
+    String::from_utf8(res.body).unwrap().lines().map(str::to_string).collect()
+}
+
+#[test]
+fn forwarded_cgi_headers_stay_within_the_caps() {
+    let headers: Vec<(String, String)> = (0..50).map(|i| (format!("X-Filler-{:02}", i), "v".repeat(100))).collect();
+    let env = forwarded_headers("        cgi_max_headers: 5\n", &headers);
+    assert_eq!(env.len(), 5, "{:?}", env);
+    assert_eq!(env[0], format!("HTTP_X_FILLER_00={}", "v".repeat(100)));
+
+    // Each is 16 + 100 bytes, so 300 bytes admit two
+    let env = forwarded_headers("        cgi_max_header_bytes: 300\n", &headers);
+    assert_eq!(env.len(), 2, "{:?}", env);
+    let total: usize = env.iter().map(|line| line.len() - 1).sum();
+    assert!(total <= 300);
+}
+
+#[test]
+fn multipart_upload_needs_a_valid_boundary() {
+    let yaml = PUT_SITE.replace("\"PUT\"", "\"POST\"");
+    let (router, dir) = site(&yaml);
+    let upload = |content_type: &str| {
+        send(&router, "POST", "/form", &[("Content-Type", content_type), ("X-Filename", "form.txt")], b"data").status_code
+    };
+    assert_eq!(upload("multipart/form-data"), 400);
+    assert_eq!(upload("multipart/form-data; boundary="), 400);
+    assert_eq!(upload("multipart/form-data; boundary=\"\""), 400);
+    assert_eq!(upload(&format!("multipart/form-data; boundary={}", "a".repeat(71))), 400);
+    assert!(!dir.0.join("form.txt").exists());
+    assert_eq!(upload("multipart/form-data; boundary=----WebKitFormBoundary7MA4YWxk"), 201);
+    assert_eq!(upload("multipart/form-data; boundary=\"gc0p4Jq0M2Yt08j34c0p\""), 201);
+    assert!(dir.0.join("form.txt").exists());
+}
+
+#[test]
+fn autoindex_marker_lists_only_its_own_directory() {
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("root: \"{root}\"\n", "root: \"{root}\"\n        autoindex_marker: \".autoindex\"\n"));
+    for sub in ["shared", "private"] {
+        fs::create_dir_all(dir.0.join(sub)).unwrap();
+        fs::write(dir.0.join(sub).join("notes.txt"), "notes").unwrap();
+    }
+    fs::write(dir.0.join("shared/.autoindex"), "").unwrap();
+    let listing = send(&router, "GET", "/shared/", &[], b"");
+    assert_eq!(listing.status_code, 200);
+    let listing = String::from_utf8(listing.body).unwrap();
+    assert!(listing.contains("notes.txt"), "{}", listing);
+    assert!(!listing.contains(".autoindex"), "{}", listing);
+    assert_eq!(send(&router, "GET", "/private/", &[], b"").status_code, 403);
+}
+
+#[test]
+fn extra_headers_go_on_every_response() {
+    let extra = "    extra_headers:\n      Alt-Svc: \"h3=\\\":443\\\"; ma=86400\"\n      Upgrade: \"h2c\"\n    routes:\n";
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("    routes:\n", extra));
+    fs::write(dir.0.join("index.html"), "home").unwrap();
+    for (path, status) in [("/index.html", 200), ("/missing.html", 404)] {
+        let res = send(&router, "GET", path, &[], b"");
+        assert_eq!(res.status_code, status);
+        assert_eq!(res.headers.get("Alt-Svc").map(String::as_str), Some("h3=\":443\"; ma=86400"), "{}", path);
+        assert_eq!(res.headers.get("Upgrade").map(String::as_str), Some("h2c"), "{}", path);
+    }
+}
+
+#[test]
+fn mime_overrides_win_and_extensions_match_in_any_case() {
+    let (router, dir) = site(&format!("mime_types:\n  \".JPG\": \"image/x-custom\"\n  js: \"text/javascript\"\n{}", DEFAULT_METHODS_SITE));
+    let content_type = |name: &str| {
+        fs::write(dir.0.join(name), "x").unwrap();
+        send(&router, "GET", &format!("/{}", name), &[], b"").headers.get("Content-Type").cloned().unwrap()
+    };
+    // The override beats the built-in entry, whichever case either side uses
+    assert_eq!(content_type("photo.jpg"), "image/x-custom");
+    assert_eq!(content_type("scan.JPG"), "image/x-custom");
+    assert_eq!(content_type("app.Js"), "text/javascript");
+    // Extensions without an override still use the built-in table
+    assert_eq!(content_type("icon.PNG"), "image/png");
+}
+
+#[test]
+fn missing_deep_path_serves_the_nearest_fallback() {
+    let fallback = |extra: &str| {
+        let yaml = DEFAULT_METHODS_SITE.replace("root: \"{root}\"\n", &format!("root: \"{{root}}\"\n        not_found_fallback: \"app.html\"\n{}", extra));
+        let (router, dir) = site(&yaml);
+        fs::create_dir_all(dir.0.join("docs")).unwrap();
+        fs::write(dir.0.join("app.html"), "root app").unwrap();
+        fs::write(dir.0.join("docs/app.html"), "docs app").unwrap();
+        (router, dir)
+    };
+    let (router, _dir) = fallback("");
+    let res = send(&router, "GET", "/docs/guide/deep/page", &[], b"");
+    assert_eq!((res.status_code, res.body.as_slice()), (200, &b"docs app"[..]));
+    assert_eq!(res.headers.get("Content-Type").map(String::as_str), Some("text/html"));
+    assert_eq!(send(&router, "GET", "/blog/2024/post", &[], b"").body, b"root app");
+
+    let (router, _dir) = fallback("        not_found_fallback_status: 404\n");
+    let res = send(&router, "GET", "/blog/post", &[], b"");
+    assert_eq!((res.status_code, res.body.as_slice()), (404, &b"root app"[..]));
+}
+
+#[test]
+fn header_sidecars_add_headers_to_served_files() {
+    let sidecar_site = |enabled: bool| {
+        let yaml = DEFAULT_METHODS_SITE.replace("root: \"{root}\"\n", &format!("root: \"{{root}}\"\n        header_sidecars: {}\n", enabled));
+        let (router, dir) = site(&yaml);
+        fs::write(dir.0.join("report.pdf"), "pdf").unwrap();
+        fs::write(dir.0.join("notes.txt"), "notes").unwrap();
+        fs::write(dir.0.join(".headers"), "# directory-wide\nCache-Control: public, max-age=60\n").unwrap();
+        fs::write(dir.0.join("report.pdf.headers"), "Cache-Control: no-store\nX-Robots-Tag: noindex\n").unwrap();
+        (router, dir)
+    };
+    let (router, _dir) = sidecar_site(true);
+    let header = |res: &Response, name: &str| res.headers.get(name).cloned();
+    let notes = send(&router, "GET", "/notes.txt", &[], b"");
+    assert_eq!(header(&notes, "Cache-Control").as_deref(), Some("public, max-age=60"));
+    // The file's own sidecar overrides the directory's
+    let report = send(&router, "GET", "/report.pdf", &[], b"");
+    assert_eq!(report.body, b"pdf");
+    assert_eq!(header(&report, "Cache-Control").as_deref(), Some("no-store"));
+    assert_eq!(header(&report, "X-Robots-Tag").as_deref(), Some("noindex"));
+    // Sidecars themselves aren't served
+    assert_eq!(send(&router, "GET", "/report.pdf.headers", &[], b"").status_code, 404);
+
+    let (router, _dir) = sidecar_site(false);
+    assert_eq!(header(&send(&router, "GET", "/report.pdf", &[], b""), "X-Robots-Tag"), None);
+}
+
+#[test]
+fn wildcard_bound_server_redirects_with_its_configured_name() {
+    let redirect_site = |host: &str| {
+        site(&format!(
+            "servers:\n  - host: \"{}\"\n    ports: [8080]\n    server_names: [\"www.example.test\", \"example.test\"]\n    absolute_redirects: true\n    routes:\n      - path: \"/old\"\n        redirect: \"/new\"\n      - path: \"/\"\n        root: \"{{root}}\"\n",
+            host
+        ))
+    };
+    for host in ["0.0.0.0", "::"] {
+        let (router, _dir) = redirect_site(host);
+        let res = send(&router, "GET", "/old", &[], b"");
+        assert_eq!(res.status_code, 301);
+        assert_eq!(res.headers.get("Location").map(String::as_str), Some("http://www.example.test/new"), "{}", host);
+    }
+    // A specific bind address is already a usable name
+    let (router, _dir) = redirect_site("127.0.0.1");
+    assert_eq!(send(&router, "GET", "/old", &[], b"").headers.get("Location").map(String::as_str), Some("http://127.0.0.1/new"));
+}
+
+#[test]
+fn route_server_tokens_override_the_servers() {
+    let routes = "    routes:\n      - path: \"/proxied\"\n        root: \"{root}\"\n        server_tokens: off\n      - path: \"/\"\n        root: \"{root}\"\n";
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("    routes:\n      - path: \"/\"\n        root: \"{root}\"\n", routes));
+    fs::write(dir.0.join("page.html"), "page").unwrap();
+    assert!(send(&router, "GET", "/page.html", &[], b"").headers.contains_key("Server"));
+    assert!(!send(&router, "GET", "/proxied/page.html", &[], b"").headers.contains_key("Server"));
+    // Error responses from the route follow it too
+    assert!(!send(&router, "GET", "/proxied/missing.html", &[], b"").headers.contains_key("Server"));
+}
+
+#[test]
+fn unchanged_directory_listing_revalidates_with_304() {
+    let (router, dir) = site(&DEFAULT_METHODS_SITE.replace("root: \"{root}\"\n", "root: \"{root}\"\n        autoindex: true\n"));
+    fs::create_dir_all(dir.0.join("docs")).unwrap();
+    fs::write(dir.0.join("docs/a.txt"), "a").unwrap();
+    let listing = send(&router, "GET", "/docs/", &[], b"");
+    assert_eq!(listing.status_code, 200);
+    let last_modified = listing.headers.get("Last-Modified").cloned().unwrap();
+
+    let revalidated = send(&router, "GET", "/docs/", &[("If-Modified-Since", &last_modified)], b"");
+    assert_eq!(revalidated.status_code, 304);
+    assert!(revalidated.body.is_empty());
+
+    // Adding an entry moves the directory's mtime past the client's copy
+    fs::write(dir.0.join("docs/b.txt"), "b").unwrap();
+    let later = std::time::SystemTime::now() + std::time::Duration::from_secs(10);
+    fs::File::open(dir.0.join("docs")).unwrap().set_modified(later).unwrap();
+    let changed = send(&router, "GET", "/docs/", &[("If-Modified-Since", &last_modified)], b"");
+    assert_eq!(changed.status_code, 200);
+    assert!(String::from_utf8(changed.body).unwrap().contains("b.txt"));
+}
+
+#[test]
+fn range_in_a_unit_other_than_bytes_gets_the_full_file() {
+    let (router, dir) = site(DEFAULT_METHODS_SITE);
+    fs::write(dir.0.join("list.json"), "[0,1,2,3,4,5,6,7,8,9,10,11]").unwrap();
+    let res = send(&router, "GET", "/list.json", &[("Range", "items=0-9")], b"");
+    assert_eq!(res.status_code, 200);
+    assert_eq!(res.body, b"[0,1,2,3,4,5,6,7,8,9,10,11]");
+    assert!(!res.headers.contains_key("Content-Range"));
+}
@@ -0,0 +1,70 @@
+use rust_localserver::server::write_pending;
+use std::io::{self, Write};
+
+/// Accepts at most `accept` bytes per write; `Ok(0)` once `accept` is zero.
+struct MockWriter {
+    accept: usize,
+    written: Vec<u8>,
+    calls: usize,
+}
+
+impl Write for MockWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.calls += 1;
+        let n = buf.len().min(self.accept);
+        self.written.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn repeated_zero_writes_give_up_instead_of_spinning() {
+    let mut writer = MockWriter { accept: 0, written: Vec::new(), calls: 0 };
+    let mut pending = b"HTTP/1.1 200 OK\r\n\r\n".to_vec();
+    let mut zero_writes = 0;
+    let mut attempts = 0;
+    while write_pending(&mut writer, &mut pending, &mut zero_writes) {
+        attempts += 1;
+        assert!(attempts < 10, "still writing after {} zero-length writes", attempts);
+    }
+    assert!(pending.is_empty());
+    assert_eq!(writer.calls, attempts + 1);
+}
+
+#[test]
+fn progress_resets_the_zero_write_count() {
+    let mut writer = MockWriter { accept: 0, written: Vec::new(), calls: 0 };
+    let mut pending = b"abcdef".to_vec();
+    let mut zero_writes = 0;
+    assert!(write_pending(&mut writer, &mut pending, &mut zero_writes));
+    writer.accept = 4;
+    assert!(write_pending(&mut writer, &mut pending, &mut zero_writes));
+    assert_eq!((pending.as_slice(), zero_writes), (&b"ef"[..], 0));
+    // One stall after progress is retried, not fatal
+    writer.accept = 0;
+    assert!(write_pending(&mut writer, &mut pending, &mut zero_writes));
+    writer.accept = 4;
+    assert!(write_pending(&mut writer, &mut pending, &mut zero_writes));
+    assert!(pending.is_empty());
+    assert_eq!(writer.written, b"abcdef");
+}
+
+#[test]
+fn write_error_drops_the_peer() {
+    struct Broken;
+    impl Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::BrokenPipe.into())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+    let mut pending = b"abc".to_vec();
+    assert!(!write_pending(&mut Broken, &mut pending, &mut 0));
+    assert!(pending.is_empty());
+}
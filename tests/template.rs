@@ -0,0 +1,24 @@
+use rust_localserver::template::render;
+
+#[test]
+fn placeholders_are_substituted() {
+    let vars = [("status", "404"), ("reason", "Not Found")];
+    assert_eq!(render("<h1>{{status}} {{ reason }}</h1>", &vars), "<h1>404 Not Found</h1>");
+    assert_eq!(render("{{status}}{{status}}", &vars), "404404");
+}
+
+#[test]
+fn values_are_html_escaped() {
+    let vars = [("path", "/<img src=x onerror=\"alert('1')\">&")];
+    assert_eq!(
+        render("Index of {{path}}", &vars),
+        "Index of /&lt;img src=x onerror=&quot;alert(&#39;1&#39;)&quot;&gt;&amp;"
+    );
+}
+
+#[test]
+fn unknown_and_unclosed_placeholders_are_left_alone() {
+    assert_eq!(render("{{missing}} and {{status", &[("status", "200")]), "{{missing}} and {{status");
+    // A value that looks like a placeholder isn't expanded again
+    assert_eq!(render("{{a}}", &[("a", "{{b}}"), ("b", "no")]), "{{b}}");
+}